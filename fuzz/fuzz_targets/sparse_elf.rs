@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use patchelfdd::patch::{Patcher, RpathKind};
+
+// Parses arbitrary bytes and plans the usual patches, nothing is ever written
+fuzz_target!(|data: &[u8]| {
+    let path = std::env::temp_dir().join(format!("patchelfdd-fuzz-{}", std::process::id()));
+    std::fs::write(&path, data).expect("Failed to write fuzz input");
+
+    let Ok(mut patcher) = Patcher::new(&path) else {
+        return;
+    };
+
+    let _ = patcher.elf.interpreter();
+    let _ = patcher.elf.needed();
+    let _ = patcher.elf.runpath();
+    let _ = patcher.elf.dynamic_stats();
+    let _ = patcher.list_candidates();
+    let _ = patcher.set_runpath("/fuzz", RpathKind::Runpath);
+    let _ = patcher.set_interpreter_path("/fuzz");
+    let _ = patcher.add_needed("libfuzz.so");
+    let _ = patcher.diff();
+});
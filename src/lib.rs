@@ -1,6 +1,9 @@
+pub mod logger;
 pub mod opts;
 pub mod patch;
 pub mod patchelfdd;
+pub mod profile;
+pub mod report;
 pub mod serialize;
 pub mod sparse_elf;
 
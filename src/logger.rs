@@ -0,0 +1,36 @@
+use colored::Colorize;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes log records to stderr, keeping stdout free for query results
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        match record.level() {
+            Level::Error => eprintln!("{}", format!("Error: {}", message).red().bold()),
+            Level::Warn => eprintln!("{}", format!("Warning: {}", message).yellow().bold()),
+            Level::Info => eprintln!("{}", message.yellow()),
+            Level::Debug | Level::Trace => eprintln!("{}", message.dimmed()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Installs the stderr logger. Only the first call has an effect.
+pub fn init(level: LevelFilter) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
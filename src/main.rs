@@ -1,18 +1,97 @@
 use patchelfdd::opts::Opts;
-use patchelfdd::Error;
+use patchelfdd::{BatchSummary, Status};
 
 use colored::Colorize;
 use structopt::StructOpt;
 
-fn run() -> Result<(), Error> {
-    let opts = Opts::from_args();
-    patchelfdd::run(opts)?;
-    Ok(())
+/// Exit code if nothing had to be changed
+const EXIT_NOTHING_TO_DO: i32 = 2;
+/// Exit code if some binaries of a batch failed and the others succeeded
+const EXIT_PARTIAL_FAILURE: i32 = 3;
+
+fn exit_code(summary: &BatchSummary) -> i32 {
+    if summary.all_failed() {
+        1
+    } else if !summary.all_succeeded() {
+        EXIT_PARTIAL_FAILURE
+    } else if summary
+        .succeeded
+        .iter()
+        .all(|(_, status)| *status == Status::NothingToDo)
+    {
+        EXIT_NOTHING_TO_DO
+    } else {
+        0
+    }
+}
+
+fn run_single(opts: Opts) -> i32 {
+    match patchelfdd::run(opts) {
+        Ok(Status::NothingToDo) => EXIT_NOTHING_TO_DO,
+        Ok(_) => 0,
+        Err(err) => {
+            eprintln!("{}", format!("Error - {}", err).red());
+            1
+        }
+    }
+}
+
+fn run_batch(opts: Opts) -> i32 {
+    let summary = patchelfdd::run_batch(opts);
+    for (bin, err) in &summary.failed {
+        eprintln!(
+            "{}",
+            format!("Error - {}: {}", bin.to_string_lossy(), err).red()
+        );
+    }
+
+    exit_code(&summary)
 }
 
 fn main() {
-    if let Err(err) = run() {
-        eprintln!("{}", format!("Error - {}", err).red());
-        std::process::exit(1);
+    let opts = Opts::from_args();
+    if !opts.use_color() {
+        colored::control::set_override(false);
     }
+
+    patchelfdd::logger::init(opts.effective_log_level());
+
+    let code = if opts.more_bins.is_empty() {
+        run_single(opts)
+    } else {
+        run_batch(opts)
+    };
+
+    std::process::exit(code);
+}
+
+#[test]
+fn test_exit_code() {
+    use patchelfdd::Error;
+    use std::path::PathBuf;
+
+    let summary = |succeeded: &[Status], failed: usize| BatchSummary {
+        succeeded: succeeded
+            .iter()
+            .map(|status| (PathBuf::from("ok"), *status))
+            .collect(),
+        failed: (0..failed)
+            .map(|_| (PathBuf::from("failed"), Error::NoDynamicSection))
+            .collect(),
+    };
+
+    assert_eq!(
+        exit_code(&summary(&[Status::Patched, Status::NothingToDo], 0)),
+        0
+    );
+    assert_eq!(exit_code(&summary(&[Status::Queried], 0)), 0);
+    assert_eq!(exit_code(&summary(&[], 2)), 1);
+    assert_eq!(
+        exit_code(&summary(&[Status::NothingToDo, Status::NothingToDo], 0)),
+        EXIT_NOTHING_TO_DO
+    );
+    assert_eq!(
+        exit_code(&summary(&[Status::NothingToDo], 1)),
+        EXIT_PARTIAL_FAILURE
+    );
 }
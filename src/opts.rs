@@ -1,31 +1,742 @@
-use std::path::PathBuf;
+use std::{io::IsTerminal, path::PathBuf};
 
 use colored::Color;
 use colored::Colorize;
+use elf::endian::AnyEndian;
+use elf::file::Class;
+use log::LevelFilter;
 use structopt::StructOpt;
 
+use crate::{
+    patch::DynamicStrategy,
+    report::OutputFormat,
+    sparse_elf::{flag_names, FLAGS_1_NAMES},
+};
+
 #[derive(StructOpt, Clone)]
 pub struct Opts {
-    /// Binary to patch
+    /// Binary to patch. With "-" it is read from stdin and the result is written to stdout
     #[structopt(long)]
     pub bin: PathBuf,
 
-    /// New runtime path
+    /// More binaries to patch with the same options, one after another
+    #[structopt(parse(from_os_str), conflicts_with_all = &conflicts_of("more-bins"))]
+    pub more_bins: Vec<PathBuf>,
+
+    /// TOML file with options to apply, named like the long options with underscores. Options
+    /// given on the command line take precedence
+    #[structopt(long)]
+    pub profile: Option<PathBuf>,
+
+    /// New runtime path. An empty path removes it, like --remove-runpath
     #[structopt(short = "r", long)]
     pub set_runpath: Option<String>,
 
+    /// New runtime path, stored as the legacy DT_RPATH instead of DT_RUNPATH. An empty path
+    /// removes it
+    #[structopt(long, conflicts_with_all = &conflicts_of("set-rpath"))]
+    pub set_rpath: Option<String>,
+
+    /// Directories to append to the existing runpath, sets DT_RUNPATH if there is none
+    #[structopt(long, conflicts_with_all = &conflicts_of("append-runpath"))]
+    pub append_runpath: Option<String>,
+
+    /// Absolute directory to set as DT_RUNPATH, written relative to the binary as $ORIGIN/<path>
+    #[structopt(long, conflicts_with_all = &conflicts_of("relative-runpath"))]
+    pub relative_runpath: Option<PathBuf>,
+
+    /// Remove DT_RUNPATH and DT_RPATH
+    #[structopt(long, conflicts_with_all = &conflicts_of("remove-runpath"))]
+    pub remove_runpath: bool,
+
+    /// With --force, replace a binary's multiple DT_RUNPATH and DT_RPATH entries with a single
+    /// one instead of failing
+    #[structopt(long)]
+    pub consolidate_runpath: bool,
+
+    /// Retag DT_RPATH as DT_RUNPATH, keeping the path. Leaves a single DT_RUNPATH entry
+    #[structopt(long, conflicts_with_all = &conflicts_of("convert-rpath-to-runpath"))]
+    pub convert_rpath_to_runpath: bool,
+
+    /// Library to add as DT_NEEDED, can be given multiple times
+    #[structopt(long, number_of_values = 1)]
+    pub add_needed: Vec<String>,
+
+    /// Library to remove from DT_NEEDED
+    #[structopt(long, conflicts_with_all = &conflicts_of("remove-needed"))]
+    pub remove_needed: Option<String>,
+
+    /// Replace a DT_NEEDED library with another one
+    #[structopt(long, number_of_values = 2, value_names = &["old", "new"])]
+    pub replace_needed: Option<Vec<String>>,
+
+    /// New DT_SONAME
+    #[structopt(long)]
+    pub set_soname: Option<String>,
+
     /// New interpreter path
     #[structopt(short = "i", long)]
     pub set_interpreter: Option<String>,
+
+    /// Binary whose interpreter path is set as the new interpreter path
+    #[structopt(long, conflicts_with_all = &conflicts_of("interpreter-from"))]
+    pub interpreter_from: Option<PathBuf>,
+
+    /// New EI_OSABI of the elf header, as a number or a name like ELFOSABI_LINUX
+    #[structopt(long, parse(try_from_str = parse_osabi))]
+    pub set_osabi: Option<u8>,
+
+    /// Make the stack executable or not, by setting PF_X of PT_GNU_STACK
+    #[structopt(long, possible_values = &["on", "off"], parse(try_from_str = parse_switch))]
+    pub set_execstack: Option<bool>,
+
+    /// DT_FLAGS_1 bit to set, as a number or a name like DF_1_ORIGIN. Can be given multiple times
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_flag_1))]
+    pub set_flag1: Vec<u64>,
+
+    /// DT_FLAGS_1 bit to clear, as a number or a name like DF_1_NOW. Can be given multiple times
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_flag_1))]
+    pub clear_flag1: Vec<u64>,
+
+    /// Leave the bytes after the new interpreter path in .interp untouched instead of zeroing them
+    #[structopt(long)]
+    pub keep_interpreter_tail: bool,
+
+    /// If the new interpreter path does not fit into .interp, move it to an unused, zero filled
+    /// gap of a read-only segment and point PT_INTERP at it
+    #[structopt(long)]
+    pub allow_relocate_interp: bool,
+
+    /// Additional .dynstr entry that may be overwritten, can be given multiple times
+    #[structopt(long, number_of_values = 1)]
+    pub sacrifice_symbol: Vec<String>,
+
+    /// Overwrite the .dynstr entry at this offset instead of searching for a candidate
+    #[structopt(long)]
+    pub sacrifice_offset: Option<usize>,
+
+    /// Candidate to sacrifice first if it is large enough, so the same symbol is overwritten
+    /// across similar binaries
+    #[structopt(long)]
+    pub prefer_candidate: Option<String>,
+
+    /// If the --prefer-candidate can't be sacrificed, sacrifice another candidate instead of
+    /// failing
+    #[structopt(long)]
+    pub allow_candidate_fallback: bool,
+
+    /// Refuse to overwrite .dynstr entries of symbols that are referenced by relocations
+    #[structopt(long)]
+    pub safe: bool,
+
+    /// Sacrifice __gmon_start__ and _ITM_deregisterTMCloneTable even if the binary seems to use
+    /// gprof or libitm. Only for binaries known not to call them
+    #[structopt(long)]
+    pub ignore_candidate_safety: bool,
+
+    /// Refuse relative interpreter paths and malformed runpaths instead of warning about them
+    #[structopt(long)]
+    pub strict: bool,
+
+    /// Where new .dynamic entries go: a spare DT_NULL entry, the entry that referenced the
+    /// sacrificed string, or the spare entry with the other as fallback
+    #[structopt(
+        long,
+        default_value = "auto",
+        possible_values = &["auto", "spare-slot", "repoint"]
+    )]
+    pub dynamic_strategy: DynamicStrategy,
+
+    /// Read and write .dynamic with the width of this class instead of the one in EI_CLASS. Only
+    /// for repairing binaries whose header lies about their layout
+    #[structopt(long, possible_values = &["elf32", "elf64"], parse(try_from_str = parse_class))]
+    pub force_class: Option<Class>,
+
+    /// Read and write .dynamic in this byte order instead of the one in EI_DATA. Only for
+    /// repairing binaries whose header lies about their layout
+    #[structopt(long, possible_values = &["little", "big"], parse(try_from_str = parse_endian))]
+    pub force_endian: Option<AnyEndian>,
+
+    /// Only patch the binary if its e_machine matches, e.g. x86-64 or EM_AARCH64, and skip it
+    /// otherwise
+    #[structopt(long, parse(try_from_str = parse_machine))]
+    pub machine_filter: Option<u16>,
+
+    /// Write the patched binary to this path and leave the original untouched. With "-" it is
+    /// written to stdout
+    #[structopt(short = "o", long)]
+    pub output: Option<PathBuf>,
+
+    /// Copy the binary to <bin><backup-suffix> before patching it
+    #[structopt(long, conflicts_with_all = &conflicts_of("backup"))]
+    pub backup: bool,
+
+    /// Suffix appended to the file name of the backup copy
+    #[structopt(long, default_value = ".orig")]
+    pub backup_suffix: String,
+
+    /// Patch a temporary copy and rename it over the binary. Needs extra disk space and breaks
+    /// hardlinks
+    #[structopt(long)]
+    pub atomic: bool,
+
+    /// Print a hexdump of the bytes that are overwritten, next to their replacement
+    #[structopt(long)]
+    pub show_diff: bool,
+
+    /// Plan the patches without writing anything, e.g. together with --show-diff or --json-plan
+    #[structopt(long)]
+    pub dry_run: bool,
+
+    /// Only check that the requested changes can be applied. Fails with the first problem, but
+    /// prints and writes nothing
+    #[structopt(long, conflicts_with_all = &conflicts_of("check"))]
+    pub check: bool,
+
+    /// Print the planned patches as a json array of {offset, length, bytes_hex, label}
+    #[structopt(long)]
+    pub json_plan: bool,
+
+    /// Don't take an advisory lock on the binary from parsing until writing it. Only for
+    /// filesystems without flock support
+    #[structopt(long)]
+    pub no_lock: bool,
+
+    /// Keep the access and modification time of the binary
+    #[structopt(long)]
+    pub preserve_timestamps: bool,
+
+    /// Re-parse the binary after patching and check that the requested values were written
+    #[structopt(long)]
+    pub verify: bool,
+
+    /// Patch elf types other than executables and shared objects, e.g. relocatable objects
+    #[structopt(long)]
+    pub allow_any_type: bool,
+
+    /// Overwrite an existing runpath instead of failing
+    #[structopt(short = "f", long)]
+    pub force: bool,
+
+    /// Print the requested changes before applying them
+    #[structopt(short = "v", long)]
+    pub verbose: bool,
+
+    /// Disable colored output. Also disabled if NO_COLOR is set or stdout is not a terminal
+    #[structopt(long)]
+    pub no_color: bool,
+
+    /// Level of the diagnostics written to stderr
+    #[structopt(
+        long,
+        default_value = "info",
+        possible_values = &["error", "warn", "info", "debug"]
+    )]
+    pub log_level: LevelFilter,
+
+    /// Only print errors to stderr, e.g. not the warning about the sacrificed .dynstr entry.
+    /// Overrides --log-level
+    #[structopt(short = "q", long)]
+    pub quiet: bool,
+
+    /// Output format of the print operations
+    #[structopt(long, default_value = "human", possible_values = &["human", "json"])]
+    pub format: OutputFormat,
+
+    /// Print the current DT_RUNPATH (or DT_RPATH)
+    #[structopt(long)]
+    pub print_runpath: bool,
+
+    /// Print the directories of the current runpath, one per line
+    #[structopt(long)]
+    pub print_rpath_entries: bool,
+
+    /// Print the current interpreter path
+    #[structopt(long)]
+    pub print_interpreter: bool,
+
+    /// Print the DT_NEEDED libraries, one per line
+    #[structopt(long)]
+    pub print_needed: bool,
+
+    /// Print the current DT_SONAME
+    #[structopt(long)]
+    pub print_soname: bool,
+
+    /// Print the EI_OSABI of the elf header
+    #[structopt(long)]
+    pub print_osabi: bool,
+
+    /// Print the set bits of DT_FLAGS and DT_FLAGS_1 by name
+    #[structopt(long)]
+    pub print_flags: bool,
+
+    /// Print true if the elf is a position independent executable, false otherwise
+    #[structopt(long)]
+    pub print_pie: bool,
+
+    /// Print which of DT_DEBUG, DT_HASH, DT_GNU_HASH, DT_BIND_NOW and DT_TEXTREL are present
+    #[structopt(long)]
+    pub print_dynamic_tags: bool,
+
+    /// Print the class, byte order, type, machine and osabi of the elf on one line
+    #[structopt(long)]
+    pub print_summary: bool,
+
+    /// Print the number of .dynamic entries and how many DT_NULL slots are free for new entries
+    #[structopt(long)]
+    pub dynamic_stats: bool,
+
+    /// List the .dynstr entries that can be overwritten, with their offset and length
+    #[structopt(long)]
+    pub list_candidates: bool,
 }
 
 impl Opts {
+    /// Whether any read-only query was requested
+    pub fn has_queries(&self) -> bool {
+        self.print_runpath
+            || self.print_rpath_entries
+            || self.print_interpreter
+            || self.print_needed
+            || self.print_soname
+            || self.print_osabi
+            || self.print_flags
+            || self.print_dynamic_tags
+            || self.print_pie
+            || self.print_summary
+            || self.dynamic_stats
+            || self.list_candidates
+    }
+
+    /// Whether any change to the binary was requested
+    pub fn has_changes(&self) -> bool {
+        self.set_runpath.is_some()
+            || self.set_rpath.is_some()
+            || self.append_runpath.is_some()
+            || self.relative_runpath.is_some()
+            || self.remove_runpath
+            || self.convert_rpath_to_runpath
+            || !self.add_needed.is_empty()
+            || self.remove_needed.is_some()
+            || self.replace_needed.is_some()
+            || self.set_soname.is_some()
+            || self.set_interpreter.is_some()
+            || self.interpreter_from.is_some()
+            || self.set_osabi.is_some()
+            || self.set_execstack.is_some()
+            || !self.set_flag1.is_empty()
+            || !self.clear_flag1.is_empty()
+    }
+
+    /// The first two given options that exclude each other, by their long names.
+    ///
+    /// The command line parser rejects the same pairs already, but options merged in afterwards,
+    /// e.g. from a profile, bypass it.
+    pub fn conflicting_options(&self) -> Option<(&'static str, &'static str)> {
+        CONFLICTS
+            .iter()
+            .find(|(first, second)| (first.given)(self) && (second.given)(self))
+            .map(|(first, second)| (first.name, second.name))
+    }
+
+    /// Level of the diagnostics to write, taking --quiet into account
+    pub fn effective_log_level(&self) -> LevelFilter {
+        if self.quiet {
+            LevelFilter::Error
+        } else {
+            self.log_level
+        }
+    }
+
+    /// Whether output should be colored, following https://no-color.org
+    pub fn use_color(&self) -> bool {
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        !self.no_color && !no_color_env && std::io::stdout().is_terminal()
+    }
+
+    /// Prints the binary and the requested changes to stderr
     pub fn print(&self) {
-        println!(
+        eprintln!(
             "{}: {}",
             "bin".color(Color::Cyan),
             self.bin.to_string_lossy().bold()
         );
-        println!("{}: {:?}", "rpath".color(Color::Yellow), self.set_runpath);
+        if let Some(output) = &self.output {
+            print_option("output", Some(&output.to_string_lossy()));
+        }
+        print_option("runpath", self.set_runpath.as_deref());
+        print_option("rpath", self.set_rpath.as_deref());
+        print_option("append runpath", self.append_runpath.as_deref());
+        if let Some(dir) = &self.relative_runpath {
+            print_option("relative runpath", Some(&dir.to_string_lossy()));
+        }
+        print_option("interpreter", self.set_interpreter.as_deref());
+        if let Some(reference) = &self.interpreter_from {
+            print_option("interpreter from", Some(&reference.to_string_lossy()));
+        }
+        print_option("soname", self.set_soname.as_deref());
+        print_option(
+            "osabi",
+            self.set_osabi
+                .map(elf::to_str::e_osabi_to_string)
+                .as_deref(),
+        );
+        if let Some(class) = self.force_class {
+            print_option("force class", Some(&format!("{:?}", class)));
+        }
+        if let Some(endianness) = self.force_endian {
+            print_option("force endian", Some(&format!("{:?}", endianness)));
+        }
+        if let Some(execstack) = self.set_execstack {
+            print_option("execstack", Some(if execstack { "on" } else { "off" }));
+        }
+        for flag in self.set_flag1.iter() {
+            print_option(
+                "set flag1",
+                Some(&flag_names(*flag, FLAGS_1_NAMES).join(" ")),
+            );
+        }
+        for flag in self.clear_flag1.iter() {
+            print_option(
+                "clear flag1",
+                Some(&flag_names(*flag, FLAGS_1_NAMES).join(" ")),
+            );
+        }
+        for library in self.add_needed.iter() {
+            print_option("add needed", Some(library));
+        }
+        print_option("remove needed", self.remove_needed.as_deref());
+        if let Some(libraries) = &self.replace_needed {
+            print_option(
+                "replace needed",
+                Some(&format!("{} -> {}", libraries[0], libraries[1])),
+            );
+        }
+        if self.remove_runpath {
+            print_option("remove runpath", Some("yes"));
+        }
+        if self.convert_rpath_to_runpath {
+            print_option("convert rpath to runpath", Some("yes"));
+        }
+    }
+}
+
+/// An option that excludes others, with how to tell from [`Opts`] whether it was given
+struct ExclusiveOption {
+    /// The long name, as the command line parser knows the option
+    name: &'static str,
+    given: fn(&Opts) -> bool,
+}
+
+const MORE_BINS: ExclusiveOption = ExclusiveOption {
+    name: "more-bins",
+    given: |opts| !opts.more_bins.is_empty(),
+};
+const SET_RUNPATH: ExclusiveOption = ExclusiveOption {
+    name: "set-runpath",
+    given: |opts| opts.set_runpath.is_some(),
+};
+const SET_RPATH: ExclusiveOption = ExclusiveOption {
+    name: "set-rpath",
+    given: |opts| opts.set_rpath.is_some(),
+};
+const APPEND_RUNPATH: ExclusiveOption = ExclusiveOption {
+    name: "append-runpath",
+    given: |opts| opts.append_runpath.is_some(),
+};
+const RELATIVE_RUNPATH: ExclusiveOption = ExclusiveOption {
+    name: "relative-runpath",
+    given: |opts| opts.relative_runpath.is_some(),
+};
+const REMOVE_RUNPATH: ExclusiveOption = ExclusiveOption {
+    name: "remove-runpath",
+    given: |opts| opts.remove_runpath,
+};
+const CONVERT_RPATH_TO_RUNPATH: ExclusiveOption = ExclusiveOption {
+    name: "convert-rpath-to-runpath",
+    given: |opts| opts.convert_rpath_to_runpath,
+};
+const ADD_NEEDED: ExclusiveOption = ExclusiveOption {
+    name: "add-needed",
+    given: |opts| !opts.add_needed.is_empty(),
+};
+const REMOVE_NEEDED: ExclusiveOption = ExclusiveOption {
+    name: "remove-needed",
+    given: |opts| opts.remove_needed.is_some(),
+};
+const REPLACE_NEEDED: ExclusiveOption = ExclusiveOption {
+    name: "replace-needed",
+    given: |opts| opts.replace_needed.is_some(),
+};
+const SET_SONAME: ExclusiveOption = ExclusiveOption {
+    name: "set-soname",
+    given: |opts| opts.set_soname.is_some(),
+};
+const SET_INTERPRETER: ExclusiveOption = ExclusiveOption {
+    name: "set-interpreter",
+    given: |opts| opts.set_interpreter.is_some(),
+};
+const INTERPRETER_FROM: ExclusiveOption = ExclusiveOption {
+    name: "interpreter-from",
+    given: |opts| opts.interpreter_from.is_some(),
+};
+const OUTPUT: ExclusiveOption = ExclusiveOption {
+    name: "output",
+    given: |opts| opts.output.is_some(),
+};
+const BACKUP: ExclusiveOption = ExclusiveOption {
+    name: "backup",
+    given: |opts| opts.backup,
+};
+const DRY_RUN: ExclusiveOption = ExclusiveOption {
+    name: "dry-run",
+    given: |opts| opts.dry_run,
+};
+const CHECK: ExclusiveOption = ExclusiveOption {
+    name: "check",
+    given: |opts| opts.check,
+};
+const SHOW_DIFF: ExclusiveOption = ExclusiveOption {
+    name: "show-diff",
+    given: |opts| opts.show_diff,
+};
+const JSON_PLAN: ExclusiveOption = ExclusiveOption {
+    name: "json-plan",
+    given: |opts| opts.json_plan,
+};
+
+/// The options that exclude each other. The first of each pair rejects the second on the command
+/// line, see [`conflicts_of`], and [`Opts::conflicting_options`] checks them after merging
+const CONFLICTS: &[(ExclusiveOption, ExclusiveOption)] = &[
+    (MORE_BINS, OUTPUT),
+    (SET_RPATH, SET_RUNPATH),
+    (APPEND_RUNPATH, SET_RUNPATH),
+    (APPEND_RUNPATH, SET_RPATH),
+    (RELATIVE_RUNPATH, SET_RUNPATH),
+    (RELATIVE_RUNPATH, SET_RPATH),
+    (RELATIVE_RUNPATH, APPEND_RUNPATH),
+    (REMOVE_RUNPATH, SET_RUNPATH),
+    (REMOVE_RUNPATH, SET_RPATH),
+    (REMOVE_RUNPATH, APPEND_RUNPATH),
+    (REMOVE_RUNPATH, RELATIVE_RUNPATH),
+    (CONVERT_RPATH_TO_RUNPATH, SET_RUNPATH),
+    (CONVERT_RPATH_TO_RUNPATH, SET_RPATH),
+    (CONVERT_RPATH_TO_RUNPATH, APPEND_RUNPATH),
+    (CONVERT_RPATH_TO_RUNPATH, RELATIVE_RUNPATH),
+    (CONVERT_RPATH_TO_RUNPATH, REMOVE_RUNPATH),
+    (REMOVE_NEEDED, SET_RUNPATH),
+    (REMOVE_NEEDED, SET_RPATH),
+    (REMOVE_NEEDED, APPEND_RUNPATH),
+    (REMOVE_NEEDED, RELATIVE_RUNPATH),
+    (REMOVE_NEEDED, REMOVE_RUNPATH),
+    (REMOVE_NEEDED, ADD_NEEDED),
+    (REMOVE_NEEDED, REPLACE_NEEDED),
+    (REMOVE_NEEDED, SET_SONAME),
+    (INTERPRETER_FROM, SET_INTERPRETER),
+    (BACKUP, OUTPUT),
+    (CHECK, DRY_RUN),
+    (CHECK, SHOW_DIFF),
+    (CHECK, JSON_PLAN),
+];
+
+/// The long names of the options that `name` excludes, for its `conflicts_with_all`
+fn conflicts_of(name: &str) -> Vec<&'static str> {
+    CONFLICTS
+        .iter()
+        .filter(|(first, _)| first.name == name)
+        .map(|(_, second)| second.name)
+        .collect()
+}
+
+/// Builds [`Opts`] without the command line parser, starting from the command line defaults.
+///
+/// Fields without a setter can be changed on the built [`Opts`].
+#[derive(Clone)]
+pub struct OptsBuilder {
+    opts: Opts,
+}
+
+impl Default for OptsBuilder {
+    fn default() -> Self {
+        // Parsing the bare minimum keeps the defaults in one place, the structopt attributes
+        let opts = Opts::from_iter_safe(["patchelfdd", "--bin", ""])
+            .expect("The command line defaults failed to parse");
+
+        Self { opts }
+    }
+}
+
+impl OptsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bin(mut self, bin: impl Into<PathBuf>) -> Self {
+        self.opts.bin = bin.into();
+        self
+    }
+
+    pub fn set_runpath(mut self, runpath: impl Into<String>) -> Self {
+        self.opts.set_runpath = Some(runpath.into());
+        self
+    }
+
+    pub fn set_rpath(mut self, rpath: impl Into<String>) -> Self {
+        self.opts.set_rpath = Some(rpath.into());
+        self
+    }
+
+    pub fn append_runpath(mut self, directories: impl Into<String>) -> Self {
+        self.opts.append_runpath = Some(directories.into());
+        self
+    }
+
+    pub fn remove_runpath(mut self) -> Self {
+        self.opts.remove_runpath = true;
+        self
+    }
+
+    /// Adds a DT_NEEDED library, can be called multiple times
+    pub fn add_needed(mut self, library: impl Into<String>) -> Self {
+        self.opts.add_needed.push(library.into());
+        self
+    }
+
+    pub fn set_soname(mut self, soname: impl Into<String>) -> Self {
+        self.opts.set_soname = Some(soname.into());
+        self
+    }
+
+    pub fn set_interpreter(mut self, interpreter_path: impl Into<String>) -> Self {
+        self.opts.set_interpreter = Some(interpreter_path.into());
+        self
+    }
+
+    pub fn set_osabi(mut self, osabi: u8) -> Self {
+        self.opts.set_osabi = Some(osabi);
+        self
+    }
+
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.opts.output = Some(output.into());
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Self {
+        self.opts.force = force;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.opts.dry_run = dry_run;
+        self
+    }
+
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.opts.verify = verify;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.opts.format = format;
+        self
+    }
+
+    pub fn build(self) -> Opts {
+        self.opts
+    }
+}
+
+/// Parses an EI_OSABI value given as a decimal or hex number, or as its ELFOSABI_ name
+pub(crate) fn parse_osabi(value: &str) -> Result<u8, String> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return u8::from_str_radix(hex, 16).map_err(|e| e.to_string());
+    }
+
+    if let Ok(osabi) = value.parse::<u8>() {
+        return Ok(osabi);
+    }
+
+    let name = value.to_uppercase();
+    (0..=u8::MAX)
+        .find(|osabi| {
+            elf::to_str::e_osabi_to_str(*osabi).is_some_and(|known| {
+                known == name || known.strip_prefix("ELFOSABI_") == Some(name.as_str())
+            })
+        })
+        .ok_or(format!("Unknown osabi {}", value))
+}
+
+/// Parses an e_machine value given as a decimal or hex number, or as its EM_ name. Dashes in the
+/// name are read as underscores, so x86-64 is EM_X86_64
+pub(crate) fn parse_machine(value: &str) -> Result<u16, String> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return u16::from_str_radix(hex, 16).map_err(|e| e.to_string());
+    }
+
+    if let Ok(machine) = value.parse::<u16>() {
+        return Ok(machine);
+    }
+
+    let name = value.to_uppercase().replace('-', "_");
+    (0..=u16::MAX)
+        .find(|machine| {
+            elf::to_str::e_machine_to_str(*machine).is_some_and(|known| {
+                known == name || known.strip_prefix("EM_") == Some(name.as_str())
+            })
+        })
+        .ok_or(format!("Unknown machine {}", value))
+}
+
+/// Parses a DT_FLAGS_1 bit given as a decimal or hex number, or as its DF_1_ name
+pub(crate) fn parse_flag_1(value: &str) -> Result<u64, String> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return u64::from_str_radix(hex, 16).map_err(|e| e.to_string());
+    }
+
+    if let Ok(flag) = value.parse::<u64>() {
+        return Ok(flag);
+    }
+
+    let name = value.to_uppercase();
+    FLAGS_1_NAMES
+        .iter()
+        .find(|(known, _)| *known == name || known.strip_prefix("DF_1_") == Some(name.as_str()))
+        .map(|(_, flag)| *flag as u64)
+        .ok_or(format!("Unknown DT_FLAGS_1 bit {}", value))
+}
+
+/// Parses "elf32" and "elf64"
+pub(crate) fn parse_class(value: &str) -> Result<Class, String> {
+    match value {
+        "elf32" => Ok(Class::ELF32),
+        "elf64" => Ok(Class::ELF64),
+        _ => Err(format!("Unknown class {}", value)),
+    }
+}
+
+/// Parses "little" and "big"
+pub(crate) fn parse_endian(value: &str) -> Result<AnyEndian, String> {
+    match value {
+        "little" => Ok(AnyEndian::Little),
+        "big" => Ok(AnyEndian::Big),
+        _ => Err(format!("Unknown byte order {}", value)),
+    }
+}
+
+/// Parses "on" and "off"
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("Expected on or off, got {}", value)),
+    }
+}
+
+fn print_option(name: &str, value: Option<&str>) {
+    match value {
+        Some(value) => eprintln!("{}: {}", name.color(Color::Yellow), value.bold()),
+        None => eprintln!("{}: {}", name.color(Color::Yellow), "<unset>".dimmed()),
     }
 }
@@ -3,13 +3,31 @@ use crate::{
     sparse_elf::{self, SparseElf},
 };
 
-use colored::Colorize;
-use std::{fs::OpenOptions, io::Seek, io::SeekFrom, io::Write, mem::size_of, path::PathBuf};
+use elf::dynamic::DynamicTable;
+use elf::endian::AnyEndian;
+use elf::section::SectionHeader;
+use elf::segment::ProgramHeader;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::{
+    fs::{self, File, FileTimes, Metadata, OpenOptions, TryLockError},
+    io::{self, Seek, SeekFrom, Write},
+    ops::Range,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use snafu::prelude::*;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
+    #[snafu(display("Failed to open file {}: {}", file_path, source))]
+    OpenElf {
+        file_path: String,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Failed to open file {} for writing: {}", file_path, source))]
     OpenElfWritable {
         file_path: String,
@@ -22,6 +40,62 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("Failed to copy {} to {}: {}", file_path, copy_path, source))]
+    CopyElf {
+        file_path: String,
+        copy_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to stat {}: {}", file_path, source))]
+    StatElf {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to set permissions of {}: {}", file_path, source))]
+    SetPermissions {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to lock {}: {}", file_path, source))]
+    LockElf {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "{} was replaced while waiting for the lock, run again to patch the new file",
+        file_path
+    ))]
+    ReplacedWhileLocking { file_path: String },
+
+    #[snafu(display("Failed to restore the timestamps of {}: {}", file_path, source))]
+    SetTimestamps {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to sync {}: {}", file_path, source))]
+    SyncElf {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to create a temporary file in {}: {}", dir_path, source))]
+    CreateTempElf {
+        dir_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to rename {} to {}: {}", temp_path, file_path, source))]
+    RenameElf {
+        file_path: String,
+        temp_path: String,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Failed to parse elf: {}", source))]
     ParseElf { source: elf::ParseError },
 
@@ -40,8 +114,55 @@ pub enum Error {
     #[snafu(display("Integer overflow"))]
     IntegerOverflow,
 
-    #[snafu(display("Did not find an appropriate entry in .dynstr to replace with DT_RUNPATH"))]
-    NoDynstrReplacementCandidate,
+    #[snafu(display(
+        "Did not find an appropriate entry in .dynstr to replace with the new string\n\
+        largest candidate: {}\n\
+        requested size: {}",
+        largest_candidate,
+        requested_size
+    ))]
+    NoDynstrReplacementCandidate {
+        largest_candidate: usize,
+        requested_size: usize,
+    },
+
+    #[snafu(display(
+        "The preferred candidate {} is missing, unsafe, already sacrificed or shorter than the \
+        {} requested bytes",
+        symbol,
+        requested_size
+    ))]
+    PreferredCandidateUnusable {
+        symbol: String,
+        requested_size: usize,
+    },
+
+    #[snafu(display(
+        "Sacrifice offset {} is outside of .dynstr (size {})",
+        offset,
+        dynstr_size
+    ))]
+    SacrificeOffsetOutOfBounds { offset: usize, dynstr_size: u64 },
+
+    #[snafu(display("Sacrifice offset {} is not at the start of a .dynstr entry", offset))]
+    SacrificeOffsetNotAtStringStart { offset: usize },
+
+    #[snafu(display(
+        "The .dynstr entry at sacrifice offset {} is too short\n\
+        entry size: {}\n\
+        requested size: {}",
+        offset,
+        entry_size,
+        requested_size
+    ))]
+    SacrificeOffsetTooShort {
+        offset: usize,
+        entry_size: usize,
+        requested_size: usize,
+    },
+
+    #[snafu(display("Refusing to overwrite {}, it is referenced by a relocation", symbol))]
+    SacrificedSymbolRelocated { symbol: String },
 
     #[snafu(display(
         "Did not find a place to add a .dynamic entry without extending. Was looking for:\n\
@@ -50,15 +171,99 @@ pub enum Error {
     ))]
     NoApplicableDynamicEntry,
 
+    #[snafu(display("Elf has no spare DT_NULL entry in .dynamic for a new entry"))]
+    NoSpareDynamicSlot,
+
+    #[snafu(display("Elf has no .dynamic entry with tag {:#x}", d_tag))]
+    NoDynamicEntry { d_tag: i64 },
+
+    #[snafu(display("Elf has no {} entry to replace", kind))]
+    NoRunpathEntry { kind: RpathKind },
+
+    #[snafu(display("Elf has neither a DT_RUNPATH nor a DT_RPATH entry to remove"))]
+    NoRunpathToRemove,
+
+    #[snafu(display("Elf has no DT_RPATH entry to convert"))]
+    NoRpathToConvert,
+
+    #[snafu(display(
+        "Refusing to repoint the {} entry whose value is the offset of the sacrificed string, it \
+        would be lost",
+        elf::to_str::d_tag_to_str(*d_tag).unwrap_or("unknown")
+    ))]
+    DynamicEntryInUse { d_tag: i64 },
+
+    #[snafu(display("Elf has no DT_NEEDED entry for {}", library))]
+    NeededNotFound { library: String },
+
+    #[snafu(display("The new soname must not be empty"))]
+    EmptySoname,
+
+    #[snafu(display(
+        "Patches at offset {} and {} overlap, refusing to write a corrupt file",
+        a_offset,
+        b_offset
+    ))]
+    OverlappingPatches { a_offset: usize, b_offset: usize },
+
+    #[snafu(display("{:?} contains a NUL byte, which would cut it short", value))]
+    InvalidPathString { value: String },
+
+    #[snafu(display(
+        "{} bytes can never fit into .dynstr, which has {} bytes",
+        requested_size,
+        dynstr_size
+    ))]
+    StringTooLong {
+        requested_size: usize,
+        dynstr_size: u64,
+    },
+
+    #[snafu(display(
+        ".dynstr has a size of {}, it holds no entries besides the leading empty string",
+        dynstr_size
+    ))]
+    DegenerateDynstr { dynstr_size: u64 },
+
+    #[snafu(display("The new {} {:?} {}", what, value, problem))]
+    SuspiciousValue {
+        what: String,
+        value: String,
+        problem: String,
+    },
+
+    #[snafu(display("Elf has no PT_GNU_STACK segment, its stack executability can't be set"))]
+    NoGnuStackSegment,
+
+    #[snafu(display("Elf has no PT_INTERP segment"))]
+    NoInterpSegment,
+
+    #[snafu(display(
+        "Found no unused, zero filled gap of {} bytes in a read-only loaded segment",
+        requested_size
+    ))]
+    NoInterpGap { requested_size: usize },
+
+    #[snafu(display("Sections {} and {} overlap, refusing to patch a malformed elf", a, b))]
+    SectionsOverlap { a: String, b: String },
+
+    #[snafu(display(
+        "Patch of {} bytes at {:#x} is not within .interp, .dynstr or .dynamic",
+        size,
+        offset
+    ))]
+    PatchOutOfBounds { offset: usize, size: usize },
+
     #[snafu(display(".dynamic is not delimited by a DT_NULL entry"))]
     DynamicSectionNotDelimited,
 
     #[snafu(display(
         "Elf .interp section is not large enough to hold the new interpreter path\n\
         .interp size: {}\n\
-        requested size: {}",
+        requested size: {} ({} bytes too long)",
         section_size,
-        requested_size
+        requested_size,
+        requested_size - section_size
     ))]
     CannotFitInterpreterPath {
         section_size: usize,
@@ -68,223 +273,2329 @@ pub enum Error {
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-#[derive(Copy, Clone)]
+/// The dynamic tag used to store a library search path
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RpathKind {
+    Runpath,
+    Rpath,
+}
+
+impl RpathKind {
+    pub fn d_tag(&self) -> i64 {
+        match self {
+            Self::Runpath => elf::abi::DT_RUNPATH,
+            Self::Rpath => elf::abi::DT_RPATH,
+        }
+    }
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::Runpath => Self::Rpath,
+            Self::Rpath => Self::Runpath,
+        }
+    }
+
+    pub fn as_string(&self) -> &'static str {
+        match self {
+            Self::Runpath => "DT_RUNPATH",
+            Self::Rpath => "DT_RPATH",
+        }
+    }
+}
+
+impl std::fmt::Display for RpathKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_string())
+    }
+}
+
+/// How a new .dynamic entry finds its place
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DynamicStrategy {
+    /// Use a spare DT_NULL entry, and repoint an existing entry if there is none
+    #[default]
+    Auto,
+    /// Only use a spare DT_NULL entry, never change an existing entry
+    SpareSlot,
+    /// Always repoint the entry that referenced the sacrificed string
+    Repoint,
+}
+
+impl FromStr for DynamicStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "spare-slot" => Ok(Self::SpareSlot),
+            "repoint" => Ok(Self::Repoint),
+            _ => Err(format!("Unknown dynamic strategy {}", s)),
+        }
+    }
+}
+
+/// A symbol that is usually unused at runtime, so its .dynstr entry can be overwritten
+pub struct BuiltinCandidate {
+    pub symbol: &'static str,
+    /// Why the guard exists, shown when it rejects the symbol
+    pub guard_reason: &'static str,
+    /// Whether `symbol` is safe to overwrite in this elf
+    is_safe: fn(&mut SparseElf) -> std::result::Result<bool, sparse_elf::Error>,
+}
+
+/// The symbols that are sacrificed without being named with --sacrifice-symbol
+pub const BUILTIN_CANDIDATES: &[BuiltinCandidate] = &[
+    // Only called when profiling with gprof, which links in mcount
+    BuiltinCandidate {
+        symbol: "__gmon_start__",
+        guard_reason: "the elf references mcount, so it is profiled with gprof",
+        is_safe: |elf| Ok(!elf.dynstr_contains("mcount")?),
+    },
+    // Weakly referenced by the crt files and only resolved if libitm is loaded
+    BuiltinCandidate {
+        symbol: "_ITM_deregisterTMCloneTable",
+        guard_reason: "the elf links libitm, so it uses transactional memory",
+        is_safe: |elf| Ok(!elf.dynstr_contains("libitm.so")?),
+    },
+];
+
+#[derive(Clone)]
 enum DynstrPatchCandidates {
-    GmonStart,
-    ITMDeregisterTMCloneTable,
+    Builtin(&'static BuiltinCandidate),
+    /// Supplied by the user, who is responsible for it being unused
+    UserSupplied(String),
 }
 
 impl DynstrPatchCandidates {
-    fn as_string(&self) -> &'static str {
+    fn as_string(&self) -> &str {
         match self {
-            Self::GmonStart => "__gmon_start__",
-            Self::ITMDeregisterTMCloneTable => "_ITM_deregisterTMCloneTable",
+            Self::Builtin(candidate) => candidate.symbol,
+            Self::UserSupplied(symbol) => symbol,
+        }
+    }
+
+    /// The user supplied symbols and the builtin ones whose guard passes, or all builtin ones
+    /// with `ignore_safety`
+    fn get_valid_candidates(
+        elf: &mut SparseElf,
+        user_symbols: &[String],
+        ignore_safety: bool,
+    ) -> Result<Vec<Self>> {
+        let mut res: Vec<Self> = user_symbols
+            .iter()
+            .map(|symbol| Self::UserSupplied(symbol.clone()))
+            .collect();
+
+        for candidate in BUILTIN_CANDIDATES {
+            if !(candidate.is_safe)(elf).context(SparseElfSnafu)? {
+                if !ignore_safety {
+                    debug!(
+                        "Not sacrificing {}, {}",
+                        candidate.symbol, candidate.guard_reason
+                    );
+                    continue;
+                }
+                debug!(
+                    "Ignoring the safety guard of {}, {}",
+                    candidate.symbol, candidate.guard_reason
+                );
+            }
+            res.push(Self::Builtin(candidate));
         }
+
+        Ok(res)
+    }
+
+    /// The valid candidates that are present in .dynstr, with their offset and length
+    fn present_candidates(
+        elf: &mut SparseElf,
+        user_symbols: &[String],
+        ignore_safety: bool,
+    ) -> Result<Vec<DynstrCandidate>> {
+        let valid_candidates = Self::get_valid_candidates(elf, user_symbols, ignore_safety)?;
+
+        Ok(elf
+            .dynstr_entries()
+            .context(SparseElfSnafu)?
+            .into_iter()
+            .filter(|(_, entry)| valid_candidates.iter().any(|c| c.as_string() == *entry))
+            .map(|(dynstr_offset, entry)| DynstrCandidate {
+                symbol: entry.to_string(),
+                dynstr_offset,
+                len: entry.len(),
+            })
+            .collect())
     }
+}
 
-    fn get_valid_candiates(elf: &mut SparseElf) -> Result<Vec<Self>> {
-        let mut res: Vec<Self> = Vec::new();
+/// A .dynstr entry that may be overwritten with a string of up to `len` bytes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynstrCandidate {
+    pub symbol: String,
+    pub dynstr_offset: usize,
+    pub len: usize,
+}
 
-        if !(elf.dynstr_contains("mcount").context(SparseElfSnafu)?) {
-            res.push(Self::GmonStart);
+/// Splits `runpath` on `:` and `,`, drops empty and repeated directories and joins the rest with
+/// `:`, the only separator the loader knows.
+pub fn normalize_runpath(runpath: &str) -> String {
+    let mut entries: Vec<&str> = Vec::new();
+    for directory in runpath.split([':', ',']).filter(|d| !d.is_empty()) {
+        if !entries.contains(&directory) {
+            entries.push(directory);
         }
+    }
+
+    entries.join(":")
+}
 
-        if !(elf.dynstr_contains("libitm.so").context(SparseElfSnafu)?) {
-            res.push(Self::ITMDeregisterTMCloneTable);
+/// Appends the `:` separated `directories` to `search_path`, skipping the ones it already has.
+///
+/// Returns None if there is nothing to append.
+fn append_to_search_path(search_path: &str, directories: &str) -> Option<String> {
+    let mut entries: Vec<&str> = search_path.split(':').filter(|e| !e.is_empty()).collect();
+    let existing_count = entries.len();
+
+    for directory in directories.split(':').filter(|d| !d.is_empty()) {
+        if !entries.contains(&directory) {
+            entries.push(directory);
         }
+    }
 
-        Ok(res)
+    if entries.len() == existing_count {
+        return None;
     }
+
+    Some(entries.join(":"))
 }
 
+/// Bytes that are written at a file offset
 #[derive(Default)]
-struct Patch {
+pub struct Patch {
     offset: usize,
     data: Vec<u8>,
+    /// The operation that planned the patch, like "runpath"
+    label: Option<&'static str>,
 }
 
-pub struct Patcher {
-    pub elf: SparseElf,
-    patches: Vec<Patch>,
-    serializer: ArchSerializer,
-    file_path: PathBuf,
-}
+impl Patch {
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 
-impl Patcher {
-    pub fn new(file_path: &PathBuf) -> Result<Self> {
-        let elf = SparseElf::new(file_path).context(SparseElfSnafu)?;
-        let serializer = ArchSerializer::new(elf.class(), elf.endianess());
-        Ok(Self {
-            elf,
-            patches: Vec::new(),
-            serializer,
-            file_path: file_path.clone(),
-        })
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.patches.is_empty()
+    pub fn label(&self) -> Option<&'static str> {
+        self.label
     }
 
-    pub fn apply(&mut self) -> Result<()> {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .open(&self.file_path)
-            .context(OpenElfWritableSnafu {
-                file_path: self.file_path.to_string_lossy(),
+    /// Writes the data at its offset of `target`, e.g. a file or an in-memory `Cursor`
+    pub fn apply_to(&self, target: &mut (impl Write + Seek)) -> Result<()> {
+        debug!(
+            "Writing {} bytes at offset {:#x}",
+            self.data.len(),
+            self.offset
+        );
+
+        target
+            .seek(SeekFrom::Start(self.offset as u64))
+            .context(SeekElfSnafu {
+                offset: self.offset,
             })?;
 
-        self.patches.sort_by_key(|p| p.offset);
+        target.write_all(&self.data).context(WriteElfSnafu)
+    }
+}
 
-        for patch in self.patches.iter() {
-            file.seek(SeekFrom::Start(patch.offset as u64))
-                .context(SeekElfSnafu {
-                    offset: patch.offset,
-                })?;
+/// A planned [`Patch`] as it is serialized by `--json-plan`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PlannedPatch {
+    pub offset: usize,
+    pub length: usize,
+    pub bytes_hex: String,
+    pub label: Option<&'static str>,
+}
 
-            file.write_all(&patch.data).context(WriteElfSnafu)?;
+impl From<&Patch> for PlannedPatch {
+    fn from(patch: &Patch) -> Self {
+        Self {
+            offset: patch.offset,
+            length: patch.data.len(),
+            bytes_hex: patch.data.iter().map(|b| format!("{:02x}", b)).collect(),
+            label: patch.label,
         }
-
-        Ok(())
     }
+}
 
-    fn add_patch(&mut self, offset: usize, size: usize) -> &mut Patch {
-        self.patches.push(Patch {
-            offset,
-            data: vec![0; size],
-        });
+/// A .dynstr entry that was overwritten to make room for a new string
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SacrificeReport {
+    pub symbol: String,
+    pub dynstr_offset: usize,
+}
 
-        self.patches.last_mut().unwrap()
+/// The bytes a patch replaces, next to the bytes it writes
+pub struct PatchDiff {
+    pub offset: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+/// File offset of the byte at `index` in a section starting at `sh_offset`.
+///
+/// `sh_offset` comes straight from the elf, so a malformed one must not overflow.
+fn section_file_offset(sh_offset: u64, index: usize) -> Result<usize> {
+    usize::try_from(sh_offset)
+        .context(IntConversionSnafu)?
+        .checked_add(index)
+        .ok_or(Error::IntegerOverflow)
+}
+
+/// The access and modification time of `metadata`, to restore them after writing
+fn file_times(metadata: &Metadata) -> FileTimes {
+    let mut times = FileTimes::new();
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
     }
 
-    pub fn set_interpreter_path(&mut self, new_interpreter_path: &str) -> Result<()> {
-        let interp_sh_size =
-            usize::try_from(self.elf.shdr_interp.sh_size).context(IntConversionSnafu)?;
+    times
+}
 
-        if interp_sh_size < new_interpreter_path.len() {
-            return Err(Error::CannotFitInterpreterPath {
-                section_size: interp_sh_size,
-                requested_size: new_interpreter_path.len(),
-            });
+/// Takes an exclusive advisory lock on `file`, waiting for another patchelfdd that holds it
+fn lock_file(file: &File, file_path: &str) -> Result<()> {
+    match file.try_lock() {
+        Ok(()) => return Ok(()),
+        Err(TryLockError::WouldBlock) => {
+            info!(
+                "Waiting for another process to finish patching {}",
+                file_path
+            )
+        }
+        Err(TryLockError::Error(source)) => {
+            return Err(Error::LockElf {
+                file_path: file_path.to_string(),
+                source,
+            })
         }
+    }
 
-        let interp_sh_offset =
-            usize::try_from(self.elf.shdr_interp.sh_offset).context(IntConversionSnafu)?;
+    file.lock().context(LockElfSnafu { file_path })
+}
 
-        let patch = self.add_patch(interp_sh_offset, new_interpreter_path.len() + 1);
-        patch.data[..new_interpreter_path.len()].copy_from_slice(new_interpreter_path.as_bytes());
+/// Fails if `file_path` no longer names `file`, e.g. because another process renamed a patched
+/// copy over it while `file` was waited for
+fn check_same_file(file: &File, file_path: &PathBuf) -> Result<()> {
+    let file_path_string = file_path.to_string_lossy().to_string();
+    let metadata = file.metadata().context(StatElfSnafu {
+        file_path: &file_path_string,
+    })?;
+    let path_metadata = fs::metadata(file_path).context(StatElfSnafu {
+        file_path: &file_path_string,
+    })?;
 
-        Ok(())
+    if metadata.dev() != path_metadata.dev() || metadata.ino() != path_metadata.ino() {
+        return Err(Error::ReplacedWhileLocking {
+            file_path: file_path_string,
+        });
     }
 
-    pub fn set_runpath(&mut self, new_runpath: &str) -> Result<()> {
-        let dynstr_entry_offset = self.set_runpath_dynstr(new_runpath)?;
-        self.set_runpath_dynamic(dynstr_entry_offset as u64)?;
+    Ok(())
+}
 
-        Ok(())
+/// Writes the `patches` to `file`
+fn write_patches(mut file: &File, patches: &[Patch]) -> Result<()> {
+    for patch in patches.iter() {
+        patch.apply_to(&mut file)?;
     }
 
-    fn set_runpath_dynstr(&mut self, new_runpath: &str) -> Result<usize> {
-        let valid_candidates = DynstrPatchCandidates::get_valid_candiates(&mut self.elf)?;
-
-        let mut dynstr_index = 1;
-        let mut dynstr_candidate: Option<DynstrPatchCandidates> = None;
+    Ok(())
+}
 
-        let dynstr_sh_size = self.elf.shdr_dynstr.sh_size;
+/// Fails if any two of the `patches`, which have to be sorted by offset, write the same bytes
+fn check_overlapping_patches(patches: &[Patch]) -> Result<()> {
+    for pair in patches.windows(2) {
+        let a_end = pair[0]
+            .offset
+            .checked_add(pair[0].data.len())
+            .ok_or(Error::IntegerOverflow)?;
 
-        let dynstr_data = self.elf.dynstr().context(SparseElfSnafu)?;
+        if a_end > pair[1].offset {
+            return Err(Error::OverlappingPatches {
+                a_offset: pair[0].offset,
+                b_offset: pair[1].offset,
+            });
+        }
+    }
 
-        while (dynstr_index as u64) < dynstr_sh_size {
-            let entry = dynstr_data.get(dynstr_index).context(ParseElfSnafu)?;
+    Ok(())
+}
 
-            if entry.len() >= new_runpath.len() {
-                if let Some(candidate) = valid_candidates.iter().find(|c| c.as_string() == entry) {
-                    dynstr_candidate = Some(*candidate);
-                    break;
-                }
-            }
+/// Why `runpath` would not work as a search path, if it looks malformed
+fn runpath_problem(runpath: &str) -> Option<&'static str> {
+    if runpath.split(':').any(|directory| directory.is_empty()) {
+        Some("has an empty directory")
+    } else if has_unknown_dynamic_string_token(runpath) {
+        Some("has a $ token other than $ORIGIN, $LIB and $PLATFORM, the loader won't expand it")
+    } else {
+        None
+    }
+}
 
-            dynstr_index += entry.len() + 1;
-        }
+/// Whether `runpath` contains a `$NAME` or `${NAME}` token other than the ones the loader
+/// expands
+fn has_unknown_dynamic_string_token(runpath: &str) -> bool {
+    const KNOWN_TOKENS: [&str; 3] = ["ORIGIN", "LIB", "PLATFORM"];
 
-        let dynstr_candidate = match dynstr_candidate {
-            Some(candidate) => candidate,
-            None => return Err(Error::NoDynstrReplacementCandidate),
+    runpath.split('$').skip(1).any(|after_dollar| {
+        let name = match after_dollar.strip_prefix('{') {
+            Some(braced) => match braced.split_once('}') {
+                Some((name, _)) => name,
+                None => return true,
+            },
+            None => after_dollar
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .next()
+                .unwrap_or_default(),
         };
 
-        println!(
-            "{}",
-            format!(
-                "Warning: Overwriting dynstr entry: {}",
-                dynstr_candidate.as_string()
-            )
-            .yellow()
-            .bold()
-        );
+        !KNOWN_TOKENS.contains(&name)
+    })
+}
 
-        let dynstr_target_offset = usize::try_from(self.elf.shdr_dynstr.sh_offset)
-            .context(IntConversionSnafu)?
-            + dynstr_index;
+/// Rejects strings that can't be written as a C string
+fn check_path_string(value: &str) -> Result<()> {
+    if value.contains('\0') {
+        return Err(Error::InvalidPathString {
+            value: value.to_string(),
+        });
+    }
 
-        let patch = self.add_patch(dynstr_target_offset, new_runpath.len() + 1);
-        patch.data[..new_runpath.len()].copy_from_slice(new_runpath.as_bytes());
+    Ok(())
+}
 
-        Ok(dynstr_index)
+/// Why `interpreter_path` would not work, if it looks wrong
+fn interpreter_path_problem(interpreter_path: &str) -> Option<&'static str> {
+    if !interpreter_path.starts_with('/') {
+        Some("is relative, it would be resolved against the working directory")
+    } else {
+        None
     }
+}
 
-    fn set_runpath_dynamic(&mut self, dynstr_entry_offset: u64) -> Result<()> {
-        let dynamic_sh_offset =
-            usize::try_from(self.elf.shdr_dynamic.sh_offset).context(IntConversionSnafu)?;
+/// File range of a section, None for sections that take no space in the file
+fn section_range(shdr: &SectionHeader) -> Option<Range<u64>> {
+    if shdr.sh_type == elf::abi::SHT_NOBITS || shdr.sh_size == 0 {
+        return None;
+    }
 
-        let dynamic_data = self.elf.dynamic().context(SparseElfSnafu)?;
+    Some(shdr.sh_offset..shdr.sh_offset.saturating_add(shdr.sh_size))
+}
 
-        let mut dyn_entry_position = dynamic_data
-            .iter()
-            .position(|d| d.d_tag == elf::abi::DT_NULL)
-            .ok_or(Error::NoApplicableDynamicEntry)?;
-
-        match dynamic_data.get(dyn_entry_position + 1) {
-            Ok(_) => {}
-            Err(e) => match e {
-                // If there are not two DT_NULL entries following each other,
-                // we try to find the Dyn entry, that referenced the .dynstr entry, that we
-                // corrupted and overwrite that.
-                elf::ParseError::BadOffset(_) => {
-                    dyn_entry_position = dynamic_data
-                        .iter()
-                        .position(|d| d.d_val() == dynstr_entry_offset)
-                        .ok_or(Error::NoApplicableDynamicEntry)?;
-                }
-                _ => return Err(Error::ParseElf { source: e }),
-            },
-        }
+/// Position of the first DT_NULL entry that is not `reserved` and followed by another entry
+/// that keeps terminating .dynamic
+fn spare_dynamic_slot(
+    dynamic_data: &DynamicTable<'_, AnyEndian>,
+    reserved: &[usize],
+) -> Option<usize> {
+    dynamic_data
+        .iter()
+        .enumerate()
+        .position(|(i, d)| d.d_tag == elf::abi::DT_NULL && !reserved.contains(&i))
+        .filter(|position| position + 1 < dynamic_data.len())
+}
 
-        let dyn_table_offset = dyn_entry_position
-            .checked_mul(match self.elf.class() {
-                elf::file::Class::ELF32 => size_of::<elf::dynamic::Elf32_Dyn>(),
-                elf::file::Class::ELF64 => size_of::<elf::dynamic::Elf64_Dyn>(),
-            })
-            .ok_or(Error::IntegerOverflow)?;
+fn ranges_overlap(a: &Range<u64>, b: &Range<u64>) -> bool {
+    a.start < b.end && b.start < a.end
+}
 
-        let dyn_entry_offset = dynamic_sh_offset
-            .checked_add(dyn_table_offset)
-            .ok_or(Error::IntegerOverflow)?;
+/// Where [`Patcher::set_interpreter_path`] writes the path
+struct InterpreterRanges {
+    ranges: Vec<Range<usize>>,
+    /// The range of PT_INTERP if it lies outside of .interp, writing to it has to be allowed
+    outside_interp: Option<Range<u64>>,
+}
 
-        let dyn_d_tag_data = self
-            .serializer
-            .bytes_from_signed_long(elf::abi::DT_RUNPATH)
-            .context(SerializingSnafu)?;
+pub struct Patcher {
+    pub elf: SparseElf,
+    patches: Vec<Patch>,
+    /// Encodes .dynamic, honoring a forced class or byte order
+    serializer: ArchSerializer,
+    /// Encodes the program and section headers, as the elf header describes them
+    header_serializer: ArchSerializer,
+    file_path: PathBuf,
+    /// Why the elf could not be opened for writing, if it was opened read-only
+    open_writable_error: Option<std::io::Error>,
+    sacrifice_symbols: Vec<String>,
+    sacrifice_offset: Option<usize>,
+    preferred_candidate: Option<String>,
+    allow_candidate_fallback: bool,
+    safe: bool,
+    ignore_candidate_safety: bool,
+    atomic: bool,
+    keep_interpreter_tail: bool,
+    allow_relocate_interp: bool,
+    strict: bool,
+    dynamic_strategy: DynamicStrategy,
+    preserve_timestamps: bool,
+    /// Whether the advisory lock taken when opening the elf is still held
+    locked: bool,
+    output: Option<PathBuf>,
+    /// Label of the operation that is being planned, attached to its patches
+    operation: Option<&'static str>,
+    sacrifices: Vec<SacrificeReport>,
+    reserved_dynamic_entries: Vec<usize>,
+    /// File ranges outside of the target sections that planned patches may write, like the
+    /// program header of a relocated PT_INTERP
+    extra_patch_ranges: Vec<Range<u64>>,
+}
 
-        let dyn_d_un_data = self
-            .serializer
-            .bytes_from_unsigned_long(dynstr_entry_offset)
-            .context(SerializingSnafu)?;
+impl Patcher {
+    /// Opens the elf for reading and writing.
+    ///
+    /// If it can't be opened for writing, it is opened read-only and `apply` will fail.
+    pub fn new(file_path: &PathBuf) -> Result<Self> {
+        Self::open(file_path, false)
+    }
 
-        let patch = self.add_patch(dyn_entry_offset, dyn_d_tag_data.len() + dyn_d_un_data.len());
+    /// Like `new`, but also accepts elf types other than executables and shared objects if
+    /// `allow_any_type` is set.
+    ///
+    /// An exclusive advisory lock is taken before the elf is parsed and held until `apply` is
+    /// done, so another patchelfdd can't plan against the same bytes. If another process holds
+    /// the lock, this waits for it.
+    pub fn open(file_path: &PathBuf, allow_any_type: bool) -> Result<Self> {
+        Self::open_elf(file_path, allow_any_type, true)
+    }
+
+    /// Like `open`, but without the advisory lock. Only for filesystems without flock support
+    pub fn open_unlocked(file_path: &PathBuf, allow_any_type: bool) -> Result<Self> {
+        Self::open_elf(file_path, allow_any_type, false)
+    }
+
+    fn open_elf(file_path: &PathBuf, allow_any_type: bool, lock: bool) -> Result<Self> {
+        let file_path_string = file_path.to_string_lossy().to_string();
+
+        let (file, open_writable_error) =
+            match OpenOptions::new().read(true).write(true).open(file_path) {
+                Ok(file) => (file, None),
+                Err(source) => (
+                    File::open(file_path).context(OpenElfSnafu {
+                        file_path: &file_path_string,
+                    })?,
+                    Some(source),
+                ),
+            };
+
+        if lock {
+            lock_file(&file, &file_path_string)?;
+            // The file we waited for may have been replaced by a patched copy in the meantime
+            check_same_file(&file, file_path)?;
+        }
+
+        let elf = SparseElf::from_file(file, allow_any_type).context(SparseElfSnafu)?;
+
+        Ok(Self::with_elf(
+            elf,
+            file_path.clone(),
+            open_writable_error,
+            lock,
+        ))
+    }
+
+    /// Patches an already opened elf, which has to be readable and writable.
+    ///
+    /// `name` is only used in error messages. Atomic mode is not supported, as it needs a path.
+    /// The elf is locked like in [`Patcher::open`].
+    pub fn from_file(file: File, name: &str, allow_any_type: bool) -> Result<Self> {
+        lock_file(&file, name)?;
+        let elf = SparseElf::from_file(file, allow_any_type).context(SparseElfSnafu)?;
+
+        Ok(Self::with_elf(elf, PathBuf::from(name), None, true))
+    }
+
+    fn with_elf(
+        elf: SparseElf,
+        file_path: PathBuf,
+        open_writable_error: Option<std::io::Error>,
+        locked: bool,
+    ) -> Self {
+        Self {
+            serializer: ArchSerializer::new(elf.class(), elf.endianess()),
+            header_serializer: ArchSerializer::new(elf.class(), elf.endianess()),
+            elf,
+            patches: Vec::new(),
+            file_path,
+            open_writable_error,
+            sacrifice_symbols: Vec::new(),
+            sacrifice_offset: None,
+            preferred_candidate: None,
+            allow_candidate_fallback: false,
+            safe: false,
+            ignore_candidate_safety: false,
+            atomic: false,
+            keep_interpreter_tail: false,
+            allow_relocate_interp: false,
+            strict: false,
+            dynamic_strategy: DynamicStrategy::default(),
+            preserve_timestamps: false,
+            locked,
+            output: None,
+            operation: None,
+            sacrifices: Vec::new(),
+            reserved_dynamic_entries: Vec::new(),
+            extra_patch_ranges: Vec::new(),
+        }
+    }
+
+    /// Allows overwriting the .dynstr entry `symbol`, in addition to the built-in candidates
+    pub fn add_sacrifice_symbol(&mut self, symbol: &str) {
+        if !self.sacrifice_symbols.iter().any(|s| s == symbol) {
+            self.sacrifice_symbols.push(symbol.to_string());
+        }
+    }
+
+    /// Overwrites the .dynstr entry at `dynstr_offset` for the next sacrifice, instead of
+    /// searching for a candidate
+    pub fn set_sacrifice_offset(&mut self, dynstr_offset: usize) {
+        self.sacrifice_offset = Some(dynstr_offset);
+    }
+
+    /// Sacrifice `symbol` if it is a valid candidate that is large enough, before any other
+    pub fn set_preferred_candidate(&mut self, symbol: Option<String>) {
+        self.preferred_candidate = symbol;
+    }
+
+    /// Search for another candidate, with a warning, if the preferred one can't be sacrificed
+    /// instead of failing
+    pub fn set_allow_candidate_fallback(&mut self, allow_candidate_fallback: bool) {
+        self.allow_candidate_fallback = allow_candidate_fallback;
+    }
+
+    /// Refuse to sacrifice .dynstr entries of symbols that are referenced by relocations
+    pub fn set_safe(&mut self, safe: bool) {
+        self.safe = safe;
+    }
+
+    /// Reads and writes .dynamic with the field width of `class`, whatever EI_CLASS says. Has to
+    /// be set before planning any patch.
+    pub fn set_force_class(&mut self, class: elf::file::Class) {
+        if class != self.elf.class() {
+            warn!(
+                "Forcing {:?} field widths, although EI_CLASS says {:?}. This is only correct if \
+                the header is wrong about the layout",
+                class,
+                self.elf.class()
+            );
+        }
+
+        self.elf.set_force_class(class);
+        self.serializer = ArchSerializer::new(class, self.elf.dynamic_endianness());
+    }
+
+    /// Reads and writes .dynamic in the byte order `endianness`, whatever EI_DATA says. Has to
+    /// be set before planning any patch.
+    pub fn set_force_endianness(&mut self, endianness: AnyEndian) {
+        if endianness != self.elf.endianess() {
+            warn!(
+                "Forcing {:?} byte order, although EI_DATA says {:?}. This is only correct if the \
+                header is wrong about the layout",
+                endianness,
+                self.elf.endianess()
+            );
+        }
+
+        self.elf.set_force_endianness(endianness);
+        self.serializer = ArchSerializer::new(self.elf.dynamic_class(), endianness);
+    }
+
+    /// Sacrifice the builtin candidates even if their guard finds them in use, see
+    /// [`BUILTIN_CANDIDATES`]
+    pub fn set_ignore_candidate_safety(&mut self, ignore_candidate_safety: bool) {
+        self.ignore_candidate_safety = ignore_candidate_safety;
+    }
+
+    /// Refuse suspicious values like relative interpreter paths, instead of only warning about them
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// The class of the elf, which decides the size of the words that patches write. A forced
+    /// class takes precedence over EI_CLASS.
+    pub fn class(&self) -> elf::file::Class {
+        self.elf.dynamic_class()
+    }
+
+    /// The byte order the patches are written in. A forced byte order takes precedence over
+    /// EI_DATA.
+    pub fn endianness(&self) -> AnyEndian {
+        self.elf.dynamic_endianness()
+    }
+
+    /// Encodes values for this elf, e.g. for custom .dynamic edits passed to
+    /// [`Patcher::set_dynamic_value`]
+    pub fn serializer(&self) -> &ArchSerializer {
+        &self.serializer
+    }
+
+    /// Whether no patches are planned, e.g. right after [`Patcher::clear_patches`]
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// Discards all planned patches, so another plan can be made without parsing the elf again.
+    ///
+    /// The sacrificed .dynstr entries and reserved .dynamic slots are released as well.
+    pub fn clear_patches(&mut self) {
+        self.patches.clear();
+        self.sacrifices.clear();
+        self.reserved_dynamic_entries.clear();
+        self.extra_patch_ranges.clear();
+    }
+
+    /// The .dynstr entries that can still be sacrificed, including user supplied symbols
+    pub fn list_candidates(&mut self) -> Result<Vec<DynstrCandidate>> {
+        let candidates = DynstrPatchCandidates::present_candidates(
+            &mut self.elf,
+            &self.sacrifice_symbols,
+            self.ignore_candidate_safety,
+        )?;
+
+        Ok(candidates
+            .into_iter()
+            .filter(|c| {
+                !self
+                    .sacrifices
+                    .iter()
+                    .any(|sacrifice| sacrifice.dynstr_offset == c.dynstr_offset)
+            })
+            .collect())
+    }
+
+    /// The .dynstr entries that the planned patches overwrite
+    pub fn sacrifices(&self) -> &[SacrificeReport] {
+        &self.sacrifices
+    }
+
+    /// The patches that `apply` will write, in the order they were planned
+    pub fn planned_patches(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    /// The planned patches in a serializable form, in the order `apply` writes them
+    pub fn plan(&self) -> Vec<PlannedPatch> {
+        self.patches.iter().map(PlannedPatch::from).collect()
+    }
+
+    /// One line describing the patches, like "Applied 2 patches: runpath, interpreter
+    /// (sacrificed __gmon_start__)"
+    pub fn summary(&self) -> String {
+        let mut labels: Vec<&str> = Vec::new();
+        for label in self.patches.iter().filter_map(Patch::label) {
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+
+        let mut summary = format!(
+            "Applied {} {}",
+            self.patches.len(),
+            if self.patches.len() == 1 {
+                "patch"
+            } else {
+                "patches"
+            }
+        );
+        if !labels.is_empty() {
+            summary.push_str(&format!(": {}", labels.join(", ")));
+        }
+        if !self.sacrifices.is_empty() {
+            let symbols: Vec<&str> = self
+                .sacrifices
+                .iter()
+                .map(|sacrifice| sacrifice.symbol.as_str())
+                .collect();
+            summary.push_str(&format!(" (sacrificed {})", symbols.join(", ")));
+        }
+
+        summary
+    }
+
+    /// Write the patches to a temporary copy and rename it over the original elf in `apply`.
+    ///
+    /// This needs space for a second copy and replaces the file, which breaks hardlinks.
+    pub fn set_atomic(&mut self, atomic: bool) {
+        self.atomic = atomic;
+    }
+
+    /// Only write the new interpreter path and its NUL terminator, instead of clearing all of
+    /// .interp
+    pub fn set_keep_interpreter_tail(&mut self, keep_interpreter_tail: bool) {
+        self.keep_interpreter_tail = keep_interpreter_tail;
+    }
+
+    /// Choose how new .dynamic entries find their place, see [`DynamicStrategy`]
+    pub fn set_dynamic_strategy(&mut self, dynamic_strategy: DynamicStrategy) {
+        self.dynamic_strategy = dynamic_strategy;
+    }
+
+    /// Move the interpreter path to an unused gap of the file if it does not fit into .interp
+    pub fn set_allow_relocate_interp(&mut self, allow_relocate_interp: bool) {
+        self.allow_relocate_interp = allow_relocate_interp;
+    }
+
+    /// Reads the bytes that the planned patches would overwrite, sorted by offset
+    pub fn diff(&self) -> Result<Vec<PatchDiff>> {
+        let mut diff = Vec::new();
+
+        for patch in self.patches.iter() {
+            let old = self
+                .elf
+                .raw_bytes_at(patch.offset as u64, patch.data.len())
+                .context(SparseElfSnafu)?;
+
+            diff.push(PatchDiff {
+                offset: patch.offset,
+                old,
+                new: patch.data.clone(),
+            });
+        }
+
+        diff.sort_by_key(|d| d.offset);
+
+        Ok(diff)
+    }
+
+    /// Restore the access and modification time of the elf after writing the patches
+    pub fn set_preserve_timestamps(&mut self, preserve_timestamps: bool) {
+        self.preserve_timestamps = preserve_timestamps;
+    }
+
+    /// Write the patched elf to `output` in `apply`, leaving the original untouched
+    pub fn set_output(&mut self, output: Option<PathBuf>) {
+        self.output = output;
+    }
+
+    /// Runs the checks of `apply` on the planned patches, without writing anything
+    pub fn check(&mut self) -> Result<()> {
+        self.patches.sort_by_key(|p| p.offset);
+        check_overlapping_patches(&self.patches)?;
+        self.check_patch_bounds()
+    }
+
+    /// Writes the planned patches and releases the advisory lock taken when opening the elf
+    pub fn apply(&mut self) -> Result<()> {
+        self.check()?;
+
+        let written = self.write_planned_patches();
+
+        if !self.locked {
+            return written;
+        }
+
+        self.locked = false;
+        let unlocked = self.elf.file().unlock().context(LockElfSnafu {
+            file_path: self.file_path.to_string_lossy(),
+        });
+
+        written.and(unlocked)
+    }
+
+    fn write_planned_patches(&mut self) -> Result<()> {
+        let file_path = self.file_path.to_string_lossy().to_string();
+        let metadata = self.elf.file().metadata().context(StatElfSnafu {
+            file_path: &file_path,
+        })?;
+
+        // Writing to a copy of the same file would truncate the elf while copying it
+        let output = self
+            .output
+            .clone()
+            .filter(|output| match fs::metadata(output) {
+                Ok(output_metadata) => {
+                    output_metadata.dev() != metadata.dev()
+                        || output_metadata.ino() != metadata.ino()
+                }
+                Err(_) => true,
+            });
+
+        // The original file is not written to, so it does not need to be writable
+        if self.atomic || output.is_some() {
+            let target_path = output.unwrap_or_else(|| self.file_path.clone());
+            return self.apply_to_copy(&target_path, &metadata);
+        }
+
+        if let Some(source) = self.open_writable_error.take() {
+            return Err(Error::OpenElfWritable { file_path, source });
+        }
+
+        write_patches(self.elf.file(), &self.patches)?;
+
+        if self.preserve_timestamps {
+            self.elf
+                .file()
+                .set_times(file_times(&metadata))
+                .context(SetTimestampsSnafu { file_path })?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every patch stays within the section it targets and that those sections don't
+    /// overlap each other or any other section, which only happens with malformed elfs.
+    fn check_patch_bounds(&mut self) -> Result<()> {
+        // An elf without interpreter has no .interp to patch
+        let targets: Vec<(&str, SectionHeader)> = [
+            (".interp", self.elf.shdr_interp),
+            (".dynstr", Some(self.elf.shdr_dynstr)),
+            (".dynamic", Some(self.elf.shdr_dynamic)),
+        ]
+        .into_iter()
+        .filter_map(|(name, shdr)| shdr.map(|shdr| (name, shdr)))
+        .collect();
+        let target_ranges: Vec<(&str, Range<u64>)> = targets
+            .iter()
+            .filter_map(|(name, shdr)| section_range(shdr).map(|range| (*name, range)))
+            .collect();
+
+        for (i, (a_name, a_range)) in target_ranges.iter().enumerate() {
+            for (b_name, b_range) in target_ranges[i + 1..].iter() {
+                if ranges_overlap(a_range, b_range) {
+                    return Err(Error::SectionsOverlap {
+                        a: a_name.to_string(),
+                        b: b_name.to_string(),
+                    });
+                }
+            }
+        }
+
+        // The targets themselves show up here as well, possibly under another name
+        for (name, shdr) in self.elf.section_headers().context(SparseElfSnafu)? {
+            if targets.iter().any(|(_, target)| *target == shdr) {
+                continue;
+            }
+
+            let Some(range) = section_range(&shdr) else {
+                continue;
+            };
+
+            if let Some((target_name, _)) = target_ranges
+                .iter()
+                .find(|(_, target_range)| ranges_overlap(target_range, &range))
+            {
+                return Err(Error::SectionsOverlap {
+                    a: target_name.to_string(),
+                    b: name,
+                });
+            }
+        }
+
+        // e_ident is patched for the osabi
+        let header_range = 0..elf::abi::EI_NIDENT as u64;
+
+        for patch in self.patches.iter() {
+            let start = patch.offset as u64;
+            let end = start
+                .checked_add(patch.data.len() as u64)
+                .ok_or(Error::IntegerOverflow)?;
+
+            let in_bounds = std::iter::once(&header_range)
+                .chain(target_ranges.iter().map(|(_, range)| range))
+                .chain(self.extra_patch_ranges.iter())
+                .any(|range| range.start <= start && end <= range.end);
+
+            if !in_bounds {
+                return Err(Error::PatchOutOfBounds {
+                    offset: patch.offset,
+                    size: patch.data.len(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a patched copy of the elf to `target_path`. In atomic mode the copy is written to
+    /// a new temporary file with a random name next to it first, which is then renamed to
+    /// `target_path`.
+    fn apply_to_copy(&self, target_path: &PathBuf, metadata: &Metadata) -> Result<()> {
+        if !self.atomic {
+            let copy_file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(target_path)
+                .context(OpenElfWritableSnafu {
+                    file_path: target_path.to_string_lossy(),
+                })?;
+            return self.write_copy(&copy_file, target_path, metadata);
+        }
+
+        let dir_path = match target_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut prefix = target_path.file_name().unwrap_or_default().to_os_string();
+        prefix.push(".");
+
+        // Created exclusively, and removed again when dropped before it is persisted
+        let temp_file = tempfile::Builder::new()
+            .prefix(&prefix)
+            .suffix(".patchelfdd.tmp")
+            .tempfile_in(dir_path)
+            .context(CreateTempElfSnafu {
+                dir_path: dir_path.to_string_lossy(),
+            })?;
+        let temp_path = temp_file.path().to_path_buf();
+
+        self.write_copy(temp_file.as_file(), &temp_path, metadata)?;
+
+        temp_file
+            .persist(target_path)
+            .map_err(|error| error.error)
+            .context(RenameElfSnafu {
+                file_path: target_path.to_string_lossy(),
+                temp_path: temp_path.to_string_lossy(),
+            })?;
+
+        Ok(())
+    }
+
+    fn write_copy(
+        &self,
+        mut copy_file: &File,
+        copy_path: &Path,
+        metadata: &Metadata,
+    ) -> Result<()> {
+        let file_path = self.file_path.to_string_lossy().to_string();
+        let copy_path_string = copy_path.to_string_lossy().to_string();
+
+        // Copy from the parsed handle, the elf might not have a path
+        let mut source = self.elf.file();
+        source
+            .seek(SeekFrom::Start(0))
+            .context(SeekElfSnafu { offset: 0usize })?;
+        io::copy(&mut source, &mut copy_file).context(CopyElfSnafu {
+            file_path: &file_path,
+            copy_path: &copy_path_string,
+        })?;
+
+        // A replacement keeps the owner of the original if it may, a new output belongs to the
+        // user. Only root may give a file away, so anyone else ends up owning the replacement
+        if self.output.is_none() {
+            if let Err(error) =
+                std::os::unix::fs::fchown(copy_file, Some(metadata.uid()), Some(metadata.gid()))
+            {
+                warn!(
+                    "Failed to keep the owner of {}, the patched binary belongs to the current \
+                    user: {}",
+                    file_path, error
+                );
+            }
+        }
+
+        write_patches(copy_file, &self.patches)?;
+
+        if self.preserve_timestamps {
+            copy_file
+                .set_times(file_times(metadata))
+                .context(SetTimestampsSnafu {
+                    file_path: &copy_path_string,
+                })?;
+        }
+
+        copy_file.sync_all().context(SyncElfSnafu {
+            file_path: &copy_path_string,
+        })?;
+
+        copy_file
+            .set_permissions(metadata.permissions())
+            .context(SetPermissionsSnafu {
+                file_path: &copy_path_string,
+            })
+    }
+
+    fn add_patch(&mut self, offset: usize, size: usize) -> &mut Patch {
+        self.patches.push(Patch {
+            offset,
+            data: vec![0; size],
+            label: self.operation,
+        });
+
+        self.patches.last_mut().unwrap()
+    }
+
+    /// Sets the EI_OSABI byte of the elf header, warning if `osabi` is not a known ABI
+    pub fn set_osabi(&mut self, osabi: u8) {
+        self.operation = Some("osabi");
+
+        if elf::to_str::e_osabi_to_str(osabi).is_none() {
+            warn!("{:#x} is not a known EI_OSABI value", osabi);
+        }
+
+        let patch = self.add_patch(elf::abi::EI_OSABI, 1);
+        patch.data[0] = osabi;
+    }
+
+    /// Sets or clears PF_X in the p_flags of PT_GNU_STACK, which decides whether the stack is
+    /// executable.
+    pub fn set_execstack(&mut self, executable: bool) -> Result<()> {
+        self.operation = Some("execstack");
+
+        let segments = self.elf.segments();
+        let stack_index = segments
+            .iter()
+            .position(|phdr| phdr.p_type == elf::abi::PT_GNU_STACK)
+            .ok_or(Error::NoGnuStackSegment)?;
+
+        let p_flags = segments[stack_index].p_flags;
+        let new_p_flags = if executable {
+            p_flags | elf::abi::PF_X
+        } else {
+            p_flags & !elf::abi::PF_X
+        };
+        if new_p_flags == p_flags {
+            return Ok(());
+        }
+
+        // p_flags follows p_type in Elf64_Phdr, but p_memsz in Elf32_Phdr
+        let word_size = self.header_serializer.word_size() as u64;
+        let p_flags_field_offset = if word_size == 8 { 4 } else { 4 + 5 * word_size };
+
+        let ehdr = self.elf.file_header();
+        let p_flags_offset = (stack_index as u64)
+            .checked_mul(ehdr.e_phentsize as u64)
+            .and_then(|offset| offset.checked_add(ehdr.e_phoff))
+            .and_then(|offset| offset.checked_add(p_flags_field_offset))
+            .ok_or(Error::IntegerOverflow)?;
+
+        let data = self.header_serializer.bytes_from_word32(new_p_flags);
+        self.extra_patch_ranges
+            .push(p_flags_offset..p_flags_offset + data.len() as u64);
+
+        let patch = self.add_patch(
+            usize::try_from(p_flags_offset).context(IntConversionSnafu)?,
+            data.len(),
+        );
+        patch.data.copy_from_slice(&data);
+
+        Ok(())
+    }
+
+    /// Writes `new_interpreter_path` to .interp.
+    ///
+    /// Returns the previous interpreter path, or None if it was not valid UTF-8.
+    pub fn set_interpreter_path(&mut self, new_interpreter_path: &str) -> Result<Option<String>> {
+        self.operation = Some("interpreter");
+
+        check_path_string(new_interpreter_path)?;
+        self.check_value(
+            "interpreter path",
+            new_interpreter_path,
+            interpreter_path_problem(new_interpreter_path),
+        )?;
+
+        let previous_interpreter_path = self.elf.interpreter().ok();
+
+        let InterpreterRanges {
+            ranges: interp_ranges,
+            outside_interp,
+        } = self.interpreter_ranges()?;
+        let interp_size = interp_ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .min()
+            .unwrap_or(0);
+
+        // The path needs one more byte for its NUL terminator
+        if interp_size <= new_interpreter_path.len() {
+            if self.allow_relocate_interp {
+                self.relocate_interpreter_path(new_interpreter_path)?;
+                return Ok(previous_interpreter_path);
+            }
+
+            return Err(Error::CannotFitInterpreterPath {
+                section_size: interp_size,
+                requested_size: new_interpreter_path.len() + 1,
+            });
+        }
+
+        // Patching outside of .interp has to be allowed explicitly
+        self.extra_patch_ranges.extend(outside_interp);
+
+        for interp_range in interp_ranges {
+            // Clear the old path completely, so no stale bytes are left after the terminator
+            let patch_size = if self.keep_interpreter_tail {
+                new_interpreter_path.len() + 1
+            } else {
+                interp_range.end - interp_range.start
+            };
+
+            let patch = self.add_patch(interp_range.start, patch_size);
+            patch.data[..new_interpreter_path.len()]
+                .copy_from_slice(new_interpreter_path.as_bytes());
+        }
+
+        Ok(previous_interpreter_path)
+    }
+
+    /// Writes the interpreter path to an unused gap and points PT_INTERP and .interp at it.
+    ///
+    /// The gap has to be zero filled, covered by a read-only PT_LOAD and outside of every
+    /// section and header table, so that nothing else uses it. The old .interp is left as is.
+    fn relocate_interpreter_path(&mut self, new_interpreter_path: &str) -> Result<()> {
+        let requested_size = new_interpreter_path.len() + 1;
+
+        let segments = self.elf.segments();
+        let interp_index = segments
+            .iter()
+            .position(|phdr| phdr.p_type == elf::abi::PT_INTERP)
+            .ok_or(Error::NoInterpSegment)?;
+
+        let (gap_offset, load) = self
+            .find_unused_gap(&segments, requested_size)?
+            .ok_or(Error::NoInterpGap { requested_size })?;
+        let gap_vaddr = load.p_vaddr + (gap_offset - load.p_offset);
+        let gap_size = requested_size as u64;
+
+        let patch = self.add_patch(
+            usize::try_from(gap_offset).context(IntConversionSnafu)?,
+            requested_size,
+        );
+        patch.data[..new_interpreter_path.len()].copy_from_slice(new_interpreter_path.as_bytes());
+        self.extra_patch_ranges
+            .push(gap_offset..gap_offset + gap_size);
+
+        let ehdr = self.elf.file_header();
+        let word_size = self.header_serializer.word_size() as u64;
+
+        // p_offset, p_vaddr, p_paddr, p_filesz and p_memsz follow the first word of the header
+        let phdr_fields_offset = (interp_index as u64)
+            .checked_mul(ehdr.e_phentsize as u64)
+            .and_then(|offset| offset.checked_add(ehdr.e_phoff))
+            .and_then(|offset| offset.checked_add(word_size))
+            .ok_or(Error::IntegerOverflow)?;
+        self.write_words(
+            phdr_fields_offset,
+            &[gap_offset, gap_vaddr, gap_vaddr, gap_size, gap_size],
+        )?;
+
+        // Keep .interp in sync, it is what readelf and this tool read back
+        let interp_shdr_index = self
+            .elf
+            .section_headers()
+            .context(SparseElfSnafu)?
+            .iter()
+            .position(|(name, shdr)| name == ".interp" && Some(*shdr) == self.elf.shdr_interp);
+
+        if let Some(interp_shdr_index) = interp_shdr_index {
+            // sh_addr, sh_offset and sh_size follow sh_name, sh_type and sh_flags
+            let shdr_fields_offset = (interp_shdr_index as u64)
+                .checked_mul(ehdr.e_shentsize as u64)
+                .and_then(|offset| offset.checked_add(ehdr.e_shoff))
+                .and_then(|offset| offset.checked_add(8 + word_size))
+                .ok_or(Error::IntegerOverflow)?;
+            self.write_words(shdr_fields_offset, &[gap_vaddr, gap_offset, gap_size])?;
+        }
+
+        warn!(
+            "The interpreter path does not fit into .interp, moved it to offset {:#x}",
+            gap_offset
+        );
+
+        Ok(())
+    }
+
+    /// Finds `size` zero bytes in a read-only PT_LOAD that no section, header table or planned
+    /// patch covers. Returns the file offset and the segment containing it.
+    fn find_unused_gap(
+        &mut self,
+        segments: &[ProgramHeader],
+        size: usize,
+    ) -> Result<Option<(u64, ProgramHeader)>> {
+        let sections = self.elf.section_headers().context(SparseElfSnafu)?;
+
+        // Without section headers there is no telling what a gap is used for
+        if sections.is_empty() {
+            return Ok(None);
+        }
+
+        let ehdr = self.elf.file_header();
+        let table_range = |offset: u64, entry_size: u16, count: usize| {
+            offset..offset.saturating_add(entry_size as u64 * count as u64)
+        };
+
+        let mut covered: Vec<Range<u64>> = sections
+            .iter()
+            .filter_map(|(_, shdr)| section_range(shdr))
+            .chain([
+                0..ehdr.e_ehsize as u64,
+                table_range(ehdr.e_phoff, ehdr.e_phentsize, ehdr.e_phnum as usize),
+                table_range(ehdr.e_shoff, ehdr.e_shentsize, sections.len()),
+            ])
+            .chain(
+                self.patches
+                    .iter()
+                    .map(|p| p.offset as u64..(p.offset + p.data.len()) as u64),
+            )
+            .collect();
+        covered.sort_by_key(|range| range.start);
+
+        for load in segments.iter().filter(|phdr| {
+            phdr.p_type == elf::abi::PT_LOAD
+                && phdr.p_flags & elf::abi::PF_R != 0
+                && phdr.p_flags & elf::abi::PF_W == 0
+        }) {
+            let load_end = load
+                .p_offset
+                .checked_add(load.p_filesz)
+                .ok_or(Error::IntegerOverflow)?;
+
+            let mut gap_start = load.p_offset;
+            for range in covered.iter().chain(std::iter::once(&(load_end..load_end))) {
+                let gap_end = range.start.min(load_end);
+
+                if gap_end > gap_start {
+                    if let Some(offset) = self.zero_run(gap_start..gap_end, size)? {
+                        return Ok(Some((offset, *load)));
+                    }
+                }
+
+                gap_start = gap_start.max(range.end);
+                if gap_start >= load_end {
+                    break;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// File offset of the first run of `size` zero bytes within `range`
+    fn zero_run(&self, range: Range<u64>, size: usize) -> Result<Option<u64>> {
+        let data = self
+            .elf
+            .raw_bytes_at(
+                range.start,
+                usize::try_from(range.end - range.start).context(IntConversionSnafu)?,
+            )
+            .context(SparseElfSnafu)?;
+
+        let mut run_start = 0;
+        for (i, byte) in data.iter().enumerate() {
+            if *byte != 0 {
+                run_start = i + 1;
+            } else if i + 1 - run_start >= size {
+                return Ok(Some(range.start + run_start as u64));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Writes consecutive class sized header fields at `offset`, which may lie outside the target
+    /// sections
+    fn write_words(&mut self, offset: u64, vals: &[u64]) -> Result<()> {
+        let data = self
+            .header_serializer
+            .bytes_from_unsigned_longs(vals)
+            .context(SerializingSnafu)?;
+
+        self.extra_patch_ranges
+            .push(offset..offset + data.len() as u64);
+
+        let patch = self.add_patch(
+            usize::try_from(offset).context(IntConversionSnafu)?,
+            data.len(),
+        );
+        patch.data.copy_from_slice(&data);
+
+        Ok(())
+    }
+
+    /// Rejects strings for .dynstr that are not a valid C string or longer than all of .dynstr,
+    /// before any candidate is searched
+    fn check_dynstr_string(&self, value: &str) -> Result<()> {
+        check_path_string(value)?;
+
+        let requested_size = value.len() + 1;
+        let dynstr_size = self.elf.shdr_dynstr.sh_size;
+
+        // Entries start at index 1, after the conventional empty string at index 0
+        if dynstr_size <= 1 {
+            return Err(Error::DegenerateDynstr { dynstr_size });
+        }
+
+        // The leading empty string can't be used
+        if requested_size as u64 >= dynstr_size {
+            return Err(Error::StringTooLong {
+                requested_size,
+                dynstr_size,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Warns about a suspicious `value`, or refuses it in strict mode
+    fn check_value(&self, what: &str, value: &str, problem: Option<&str>) -> Result<()> {
+        let Some(problem) = problem else {
+            return Ok(());
+        };
+
+        if self.strict {
+            return Err(Error::SuspiciousValue {
+                what: what.to_string(),
+                value: value.to_string(),
+                problem: problem.to_string(),
+            });
+        }
+
+        warn!("The new {} {:?} {}", what, value, problem);
+
+        Ok(())
+    }
+
+    /// File ranges the interpreter path has to be written to.
+    ///
+    /// The loader reads PT_INTERP, so that comes first. If .interp is somewhere else, it is kept
+    /// in sync as long as it doesn't partially overlap PT_INTERP.
+    fn interpreter_ranges(&self) -> Result<InterpreterRanges> {
+        let shdr_interp = self
+            .elf
+            .shdr_interp
+            .ok_or(sparse_elf::Error::NoInterpSection)
+            .context(SparseElfSnafu)?;
+        let section_range = section_range(&shdr_interp);
+
+        let mut outside_interp = None;
+        let ranges: Vec<Range<u64>> = match self.elf.segment(elf::abi::PT_INTERP) {
+            None => section_range.into_iter().collect(),
+            Some(phdr_interp) => {
+                if phdr_interp.p_align > 1 && phdr_interp.p_offset % phdr_interp.p_align != 0 {
+                    warn!(
+                        "PT_INTERP at {:#x} is not aligned to its p_align of {:#x}",
+                        phdr_interp.p_offset, phdr_interp.p_align
+                    );
+                }
+
+                let segment_range = phdr_interp.p_offset
+                    ..phdr_interp
+                        .p_offset
+                        .checked_add(phdr_interp.p_filesz)
+                        .ok_or(Error::IntegerOverflow)?;
+
+                match section_range {
+                    Some(section_range) if section_range == segment_range => vec![segment_range],
+                    _ => {
+                        warn!(
+                            "PT_INTERP at {:#x} ({} bytes) does not match .interp at {:#x} \
+                            ({} bytes), writing the interpreter path where the loader reads it",
+                            phdr_interp.p_offset,
+                            phdr_interp.p_filesz,
+                            shdr_interp.sh_offset,
+                            shdr_interp.sh_size
+                        );
+
+                        outside_interp = Some(segment_range.clone());
+
+                        match section_range {
+                            Some(section_range)
+                                if !ranges_overlap(&section_range, &segment_range) =>
+                            {
+                                vec![segment_range, section_range]
+                            }
+                            _ => vec![segment_range],
+                        }
+                    }
+                }
+            }
+        };
+
+        let ranges = ranges
+            .into_iter()
+            .map(|range| {
+                Ok(usize::try_from(range.start).context(IntConversionSnafu)?
+                    ..usize::try_from(range.end).context(IntConversionSnafu)?)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(InterpreterRanges {
+            ranges,
+            outside_interp,
+        })
+    }
+
+    /// Adds a new `kind` entry pointing at `new_runpath` in a sacrificed .dynstr entry. The
+    /// runpath is normalized with [`normalize_runpath`] first.
+    ///
+    /// Returns the runpath the elf had before, if any.
+    pub fn set_runpath(&mut self, new_runpath: &str, kind: RpathKind) -> Result<Option<String>> {
+        self.operation = Some("runpath");
+
+        let new_runpath = &normalize_runpath(new_runpath);
+
+        self.check_dynstr_string(new_runpath)?;
+        self.check_value("runpath", new_runpath, runpath_problem(new_runpath))?;
+
+        let previous_runpath = self.elf.runpath().context(SparseElfSnafu)?;
+
+        let dynstr_entry_offset = self.sacrifice_dynstr_entry(new_runpath)?;
+        self.set_runpath_dynamic(dynstr_entry_offset as u64, kind)?;
+
+        Ok(previous_runpath)
+    }
+
+    /// Replaces the string of an existing `existing_kind` entry and retags it as `kind`.
+    ///
+    /// The string is overwritten in place if the new runpath fits, otherwise a dynstr candidate is
+    /// sacrificed and the existing .dynamic entry is pointed at it.
+    pub fn replace_runpath(
+        &mut self,
+        new_runpath: &str,
+        existing_kind: RpathKind,
+        kind: RpathKind,
+    ) -> Result<()> {
+        self.operation = Some("runpath");
+
+        let new_runpath = &normalize_runpath(new_runpath);
+
+        self.check_dynstr_string(new_runpath)?;
+        self.check_value("runpath", new_runpath, runpath_problem(new_runpath))?;
+
+        if !self.replace_dynamic_string(existing_kind.d_tag(), kind.d_tag(), new_runpath)? {
+            return Err(Error::NoRunpathEntry {
+                kind: existing_kind,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Retags the first DT_RPATH entry as DT_RUNPATH, keeping the path string it points to.
+    ///
+    /// The other DT_RPATH entries are neutralized like in `remove_runpath`, so the elf ends up with
+    /// a single DT_RUNPATH. An existing DT_RUNPATH is kept instead, since the loader already
+    /// ignores DT_RPATH next to it.
+    pub fn convert_rpath_to_runpath(&mut self) -> Result<()> {
+        self.operation = Some("convert rpath");
+
+        let dynamic_data = self.elf.dynamic().context(SparseElfSnafu)?;
+
+        let rpath_positions: Vec<usize> = dynamic_data
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.d_tag == RpathKind::Rpath.d_tag())
+            .map(|(i, _)| i)
+            .collect();
+
+        if rpath_positions.is_empty() {
+            return Err(Error::NoRpathToConvert);
+        }
+
+        let mut extra_positions = rpath_positions.as_slice();
+        if !self
+            .elf
+            .dynamic_contains(RpathKind::Runpath.d_tag())
+            .context(SparseElfSnafu)?
+        {
+            let d_tag_data = self
+                .serializer
+                .bytes_from_signed_long(RpathKind::Runpath.d_tag())
+                .context(SerializingSnafu)?;
+
+            let d_tag_offset = self.dynamic_entry_offset(rpath_positions[0])?;
+            let patch = self.add_patch(d_tag_offset, d_tag_data.len());
+            patch.data.copy_from_slice(d_tag_data.bytes());
+            extra_positions = &rpath_positions[1..];
+        }
+
+        for dyn_entry_position in extra_positions {
+            self.write_dynamic_entry(*dyn_entry_position, elf::abi::DT_DEBUG, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends `directories` to the existing runpath, or sets it if the elf has none.
+    ///
+    /// The existing DT_RUNPATH or DT_RPATH keeps its kind. Directories that are already part of
+    /// the runpath are skipped.
+    pub fn append_runpath(&mut self, directories: &str) -> Result<()> {
+        self.operation = Some("runpath");
+
+        check_path_string(directories)?;
+
+        let mut existing_kind = None;
+        for kind in [RpathKind::Runpath, RpathKind::Rpath] {
+            if self
+                .elf
+                .dynamic_contains(kind.d_tag())
+                .context(SparseElfSnafu)?
+            {
+                existing_kind = Some(kind);
+                break;
+            }
+        }
+
+        let existing_runpath = self
+            .elf
+            .runpath()
+            .context(SparseElfSnafu)?
+            .unwrap_or_default();
+
+        let new_runpath = match append_to_search_path(&existing_runpath, directories) {
+            Some(new_runpath) => new_runpath,
+            None => return Ok(()),
+        };
+
+        match existing_kind {
+            Some(kind) => self.replace_runpath(&new_runpath, kind, kind),
+            None => self
+                .set_runpath(&new_runpath, RpathKind::Runpath)
+                .map(|_| ()),
+        }
+    }
+
+    /// Sets DT_SONAME, replacing the existing name or adding a new entry if there is none.
+    ///
+    /// Returns the previous soname, if any.
+    pub fn set_soname(&mut self, new_soname: &str) -> Result<Option<String>> {
+        self.operation = Some("soname");
+
+        if new_soname.is_empty() {
+            return Err(Error::EmptySoname);
+        }
+
+        self.check_dynstr_string(new_soname)?;
+
+        let previous_soname = self.elf.soname().context(SparseElfSnafu)?;
+
+        if self.replace_dynamic_string(elf::abi::DT_SONAME, elf::abi::DT_SONAME, new_soname)? {
+            return Ok(previous_soname);
+        }
+
+        let dynstr_entry_offset = self.sacrifice_dynstr_entry(new_soname)? as u64;
+        let dyn_entry_position =
+            self.reserve_dynamic_entry(dynstr_entry_offset, elf::abi::DT_SONAME)?;
+        self.write_dynamic_entry(dyn_entry_position, elf::abi::DT_SONAME, dynstr_entry_offset)?;
+
+        Ok(previous_soname)
+    }
+
+    /// Points the first .dynamic entry with `d_tag` at `new_string` and retags it as `new_d_tag`.
+    ///
+    /// The referenced string is overwritten in place if `new_string` fits and no other string
+    /// shares a suffix of it, otherwise a dynstr candidate is sacrificed. Returns false if there is no entry with `d_tag`.
+    fn replace_dynamic_string(
+        &mut self,
+        d_tag: i64,
+        new_d_tag: i64,
+        new_string: &str,
+    ) -> Result<bool> {
+        let Some(dyn_entry_position) = self.elf.find_dynamic(d_tag).context(SparseElfSnafu)? else {
+            return Ok(false);
+        };
+        let old_dynstr_offset = self
+            .elf
+            .dynamic()
+            .context(SparseElfSnafu)?
+            .get(dyn_entry_position)
+            .context(ParseElfSnafu)?
+            .d_val();
+
+        let old_dynstr_index = usize::try_from(old_dynstr_offset).context(IntConversionSnafu)?;
+
+        let old_string_len = self
+            .elf
+            .dynstr_at(old_dynstr_index)
+            .context(SparseElfSnafu)?
+            .len();
+
+        if new_string.len() > old_string_len
+            || self.dynstr_suffix_referenced(old_dynstr_index, old_string_len)?
+        {
+            let dynstr_entry_offset = self.sacrifice_dynstr_entry(new_string)?;
+            self.write_dynamic_entry(dyn_entry_position, new_d_tag, dynstr_entry_offset as u64)?;
+            return Ok(true);
+        }
+
+        if new_d_tag != d_tag {
+            self.write_dynamic_entry(dyn_entry_position, new_d_tag, old_dynstr_offset)?;
+        }
+
+        self.overwrite_dynstr_entry(old_dynstr_index, old_string_len, new_string)?;
+
+        Ok(true)
+    }
+
+    /// Neutralizes every DT_RUNPATH and DT_RPATH entry by turning it into a zeroed DT_DEBUG entry.
+    ///
+    /// Using DT_DEBUG instead of DT_NULL keeps the entries after it reachable for the loader.
+    pub fn remove_runpath(&mut self) -> Result<()> {
+        self.operation = Some("remove runpath");
+
+        let dynamic_data = self.elf.dynamic().context(SparseElfSnafu)?;
+
+        let runpath_positions: Vec<usize> = dynamic_data
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| {
+                d.d_tag == RpathKind::Runpath.d_tag() || d.d_tag == RpathKind::Rpath.d_tag()
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        if runpath_positions.is_empty() {
+            return Err(Error::NoRunpathToRemove);
+        }
+
+        for dyn_entry_position in runpath_positions {
+            self.write_dynamic_entry(dyn_entry_position, elf::abi::DT_DEBUG, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Neutralizes every DT_RUNPATH and DT_RPATH entry except the first `keep_kind` one, like
+    /// `remove_runpath`. Returns how many entries were removed.
+    pub fn remove_extra_runpaths(&mut self, keep_kind: RpathKind) -> Result<usize> {
+        self.operation = Some("runpath");
+
+        let keep_position = self
+            .elf
+            .find_dynamic(keep_kind.d_tag())
+            .context(SparseElfSnafu)?;
+        let dynamic_data = self.elf.dynamic().context(SparseElfSnafu)?;
+
+        let extra_positions: Vec<usize> = dynamic_data
+            .iter()
+            .enumerate()
+            .filter(|(i, d)| {
+                Some(*i) != keep_position
+                    && (d.d_tag == RpathKind::Runpath.d_tag()
+                        || d.d_tag == RpathKind::Rpath.d_tag())
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        for dyn_entry_position in extra_positions.iter() {
+            self.write_dynamic_entry(*dyn_entry_position, elf::abi::DT_DEBUG, 0)?;
+        }
+
+        Ok(extra_positions.len())
+    }
+
+    /// Overwrites an unused .dynstr entry with `new_string` and returns its .dynstr offset.
+    fn sacrifice_dynstr_entry(&mut self, new_string: &str) -> Result<usize> {
+        let sacrificed_entries = match self.sacrifice_offset.take() {
+            Some(dynstr_index) => vec![(
+                dynstr_index,
+                self.dynstr_entry_at_sacrifice_offset(dynstr_index, new_string)?,
+            )],
+            None => self.find_dynstr_candidate(new_string)?,
+        };
+
+        for (dynstr_index, sacrificed_entry) in sacrificed_entries.iter() {
+            if self
+                .elf
+                .is_relocation_target(*dynstr_index)
+                .context(SparseElfSnafu)?
+            {
+                if self.safe {
+                    return Err(Error::SacrificedSymbolRelocated {
+                        symbol: sacrificed_entry.clone(),
+                    });
+                }
+
+                warn!(
+                    "{} is referenced by a relocation, symbol lookup will break",
+                    sacrificed_entry
+                );
+            }
+
+            warn!("Overwriting dynstr entry: {}", sacrificed_entry);
+        }
+
+        let dynstr_index = sacrificed_entries[0].0;
+        let dynstr_target_offset =
+            section_file_offset(self.elf.shdr_dynstr.sh_offset, dynstr_index)?;
+
+        self.sacrifices.extend(
+            sacrificed_entries
+                .into_iter()
+                .map(|(dynstr_offset, symbol)| SacrificeReport {
+                    symbol,
+                    dynstr_offset,
+                }),
+        );
+
+        let patch = self.add_patch(dynstr_target_offset, new_string.len() + 1);
+        patch.data[..new_string.len()].copy_from_slice(new_string.as_bytes());
+
+        Ok(dynstr_index)
+    }
+
+    /// Validates a user supplied .dynstr offset and returns the entry starting there.
+    fn dynstr_entry_at_sacrifice_offset(
+        &mut self,
+        dynstr_index: usize,
+        new_string: &str,
+    ) -> Result<String> {
+        let dynstr_sh_size = self.elf.shdr_dynstr.sh_size;
+
+        if dynstr_index == 0 || dynstr_index as u64 >= dynstr_sh_size {
+            return Err(Error::SacrificeOffsetOutOfBounds {
+                offset: dynstr_index,
+                dynstr_size: dynstr_sh_size,
+            });
+        }
+
+        let dynstr_data = self.elf.dynstr().context(SparseElfSnafu)?;
+
+        // The previous byte has to be the NUL terminator of the preceding entry
+        if !dynstr_data
+            .get_raw(dynstr_index - 1)
+            .context(ParseElfSnafu)?
+            .is_empty()
+        {
+            return Err(Error::SacrificeOffsetNotAtStringStart {
+                offset: dynstr_index,
+            });
+        }
+
+        let entry = dynstr_data.get(dynstr_index).context(ParseElfSnafu)?;
+
+        if entry.len() < new_string.len() {
+            return Err(Error::SacrificeOffsetTooShort {
+                offset: dynstr_index,
+                entry_size: entry.len(),
+                requested_size: new_string.len(),
+            });
+        }
+
+        Ok(entry.to_string())
+    }
+
+    /// Searches .dynstr for a candidate that can hold `new_string` and was not sacrificed yet.
+    ///
+    /// If no single candidate is long enough, a run of adjacent candidates is merged. Returns the
+    /// .dynstr offsets and names of the sacrificed entries, starting with the one to write to.
+    fn find_dynstr_candidate(&mut self, new_string: &str) -> Result<Vec<(usize, String)>> {
+        let valid_candidates = DynstrPatchCandidates::get_valid_candidates(
+            &mut self.elf,
+            &self.sacrifice_symbols,
+            self.ignore_candidate_safety,
+        )?;
+
+        let usable_entries: Vec<(usize, String)> = self
+            .elf
+            .dynstr_entries()
+            .context(SparseElfSnafu)?
+            .into_iter()
+            .map(|(dynstr_index, entry)| (dynstr_index, entry.to_string()))
+            .collect();
+
+        let is_usable = |dynstr_index: &usize, entry: &str| {
+            !self
+                .sacrifices
+                .iter()
+                .any(|sacrifice| sacrifice.dynstr_offset == *dynstr_index)
+                && valid_candidates.iter().any(|c| c.as_string() == entry)
+        };
+
+        // Once sacrificed, the preferred candidate has served its purpose for later strings
+        let preferred = self.preferred_candidate.as_ref().filter(|preferred| {
+            !self
+                .sacrifices
+                .iter()
+                .any(|sacrifice| sacrifice.symbol == **preferred)
+        });
+        if let Some(preferred) = preferred {
+            match usable_entries.iter().find(|(i, entry)| {
+                entry == preferred && entry.len() >= new_string.len() && is_usable(i, entry)
+            }) {
+                Some((dynstr_index, entry)) => return Ok(vec![(*dynstr_index, entry.clone())]),
+                None if self.allow_candidate_fallback => warn!(
+                    "The preferred candidate {} is not usable for {}, searching for another",
+                    preferred, new_string
+                ),
+                None => {
+                    return Err(Error::PreferredCandidateUnusable {
+                        symbol: preferred.clone(),
+                        requested_size: new_string.len(),
+                    })
+                }
+            }
+        }
+
+        if let Some((dynstr_index, entry)) = usable_entries
+            .iter()
+            .find(|(i, entry)| entry.len() >= new_string.len() && is_usable(i, entry))
+        {
+            return Ok(vec![(*dynstr_index, entry.clone())]);
+        }
+
+        // Strings can be referenced from the middle of another one, when the linker merged a
+        // suffix. Such entries can't be part of a run, as the reference would break.
+        let references = self.elf.dynstr_references().context(SparseElfSnafu)?;
+
+        // Reported if nothing fits, so the user knows how much to shorten the string
+        let mut largest_candidate = usable_entries
+            .iter()
+            .filter(|(i, entry)| is_usable(i, entry))
+            .map(|(_, entry)| entry.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut run: Vec<(usize, String)> = Vec::new();
+        for (dynstr_index, entry) in usable_entries {
+            let entry_end = dynstr_index + entry.len();
+
+            let referenced_inside = references
+                .iter()
+                .any(|r| *r > dynstr_index && *r <= entry_end);
+
+            if !is_usable(&dynstr_index, &entry) || referenced_inside {
+                run.clear();
+                continue;
+            }
+
+            run.push((dynstr_index, entry));
+
+            // The span covers the NUL terminators between the entries, the last one is kept
+            let run_size = entry_end - run[0].0;
+            if run_size >= new_string.len() {
+                return Ok(run);
+            }
+
+            largest_candidate = largest_candidate.max(run_size);
+        }
+
+        Err(Error::NoDynstrReplacementCandidate {
+            largest_candidate,
+            requested_size: new_string.len(),
+        })
+    }
+
+    fn set_runpath_dynamic(&mut self, dynstr_entry_offset: u64, kind: RpathKind) -> Result<()> {
+        let dyn_entry_position = self.reserve_dynamic_entry(dynstr_entry_offset, kind.d_tag())?;
+        self.write_dynamic_entry(dyn_entry_position, kind.d_tag(), dynstr_entry_offset)
+    }
+
+    /// Finds a .dynamic entry that can hold a new entry without extending the section.
+    ///
+    /// Depending on the [`DynamicStrategy`] this is a spare DT_NULL entry, or the entry that
+    /// referenced the sacrificed .dynstr entry at `dynstr_entry_offset`. Only a search path or
+    /// an entry with the `new_d_tag` that is written is repointed, any other entry still carries
+    /// meaning or only holds the same number by coincidence.
+    fn reserve_dynamic_entry(&mut self, dynstr_entry_offset: u64, new_d_tag: i64) -> Result<usize> {
+        let dynamic_data = self.elf.dynamic().context(SparseElfSnafu)?;
+
+        let spare_slot = spare_dynamic_slot(&dynamic_data, &self.reserved_dynamic_entries);
+
+        let dyn_entry_position = match (self.dynamic_strategy, spare_slot) {
+            (DynamicStrategy::Auto | DynamicStrategy::SpareSlot, Some(position)) => position,
+            (DynamicStrategy::SpareSlot, None) => return Err(Error::NoSpareDynamicSlot),
+            // Find the Dyn entry that referenced the .dynstr entry that we corrupted and
+            // overwrite that
+            (DynamicStrategy::Auto, None) | (DynamicStrategy::Repoint, _) => {
+                let (position, d_tag) = dynamic_data
+                    .iter()
+                    .enumerate()
+                    .map(|(i, d)| (i, d.d_tag, d.d_val()))
+                    .find(|(i, _, d_val)| {
+                        *d_val == dynstr_entry_offset && !self.reserved_dynamic_entries.contains(i)
+                    })
+                    .map(|(i, d_tag, _)| (i, d_tag))
+                    .ok_or(Error::NoApplicableDynamicEntry)?;
+
+                let repointable = d_tag == new_d_tag
+                    || d_tag == elf::abi::DT_RPATH
+                    || d_tag == elf::abi::DT_RUNPATH;
+                if !repointable {
+                    return Err(Error::DynamicEntryInUse { d_tag });
+                }
+
+                position
+            }
+        };
+
+        self.reserved_dynamic_entries.push(dyn_entry_position);
+
+        Ok(dyn_entry_position)
+    }
+
+    /// Reserves a spare DT_NULL entry for a new entry that references no string, so there is no
+    /// entry to repoint
+    fn reserve_spare_dynamic_entry(&mut self) -> Result<usize> {
+        let dynamic_data = self.elf.dynamic().context(SparseElfSnafu)?;
+
+        let dyn_entry_position = spare_dynamic_slot(&dynamic_data, &self.reserved_dynamic_entries)
+            .ok_or(Error::NoSpareDynamicSlot)?;
+        self.reserved_dynamic_entries.push(dyn_entry_position);
+
+        Ok(dyn_entry_position)
+    }
+
+    /// Sets the `set` bits and clears the `clear` bits of DT_FLAGS_1, e.g. DF_1_ORIGIN.
+    ///
+    /// An elf without DT_FLAGS_1 gets a new entry in a spare DT_NULL slot, if any bit is set.
+    pub fn update_flags_1(&mut self, set: u64, clear: u64) -> Result<()> {
+        self.operation = Some("flags1");
+
+        let Some(flags_1) = self.elf.flags_1().context(SparseElfSnafu)? else {
+            if set == 0 {
+                return Ok(());
+            }
+
+            let dyn_entry_position = self.reserve_spare_dynamic_entry()?;
+            return self.write_dynamic_entry(dyn_entry_position, elf::abi::DT_FLAGS_1, set);
+        };
+
+        let new_flags_1 = (flags_1 | set) & !clear;
+        if new_flags_1 == flags_1 {
+            return Ok(());
+        }
+
+        self.write_dynamic_value(elf::abi::DT_FLAGS_1, new_flags_1)
+    }
+
+    pub fn add_needed(&mut self, library: &str) -> Result<()> {
+        self.operation = Some("add needed");
+
+        self.check_dynstr_string(library)?;
+
+        let dynstr_entry_offset = self.sacrifice_dynstr_entry(library)? as u64;
+        let dyn_entry_position =
+            self.reserve_dynamic_entry(dynstr_entry_offset, elf::abi::DT_NEEDED)?;
+        self.write_dynamic_entry(dyn_entry_position, elf::abi::DT_NEEDED, dynstr_entry_offset)
+    }
+
+    /// Removes every DT_NEEDED entry naming `library`.
+    ///
+    /// The following entries are shifted up and the freed slots at the end are filled with
+    /// DT_NULL, so this has to be the only edit of .dynamic planned on this patcher.
+    pub fn remove_needed(&mut self, library: &str) -> Result<()> {
+        self.operation = Some("remove needed");
+
+        let dynamic_entries: Vec<(i64, u64)> = self
+            .elf
+            .dynamic()
+            .context(SparseElfSnafu)?
+            .iter()
+            .map(|d| (d.d_tag, d.d_val()))
+            .collect();
+
+        let mut compacted_entries: Vec<(i64, u64)> = Vec::with_capacity(dynamic_entries.len());
+        for (d_tag, d_val) in dynamic_entries.iter().copied() {
+            if d_tag == elf::abi::DT_NEEDED {
+                let dynstr_index = usize::try_from(d_val).context(IntConversionSnafu)?;
+                if self.elf.dynstr_at(dynstr_index).context(SparseElfSnafu)? == library {
+                    continue;
+                }
+            }
+            compacted_entries.push((d_tag, d_val));
+        }
+
+        if compacted_entries.len() == dynamic_entries.len() {
+            return Err(Error::NeededNotFound {
+                library: library.to_string(),
+            });
+        }
+
+        compacted_entries.resize(dynamic_entries.len(), (elf::abi::DT_NULL, 0));
+
+        for (dyn_entry_position, (old_entry, new_entry)) in dynamic_entries
+            .into_iter()
+            .zip(compacted_entries)
+            .enumerate()
+        {
+            if old_entry != new_entry {
+                self.write_dynamic_entry(dyn_entry_position, new_entry.0, new_entry.1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces every DT_NEEDED entry naming `old_library` with `new_library` and returns the
+    /// number of replaced entries.
+    ///
+    /// The name is overwritten in place if it fits and no other string shares a suffix of it,
+    /// otherwise a dynstr candidate is sacrificed and the entries are pointed at it.
+    pub fn replace_needed(&mut self, old_library: &str, new_library: &str) -> Result<usize> {
+        self.operation = Some("replace needed");
+
+        self.check_dynstr_string(new_library)?;
+
+        let needed_entries: Vec<(usize, usize)> = self
+            .elf
+            .dynamic()
+            .context(SparseElfSnafu)?
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.d_tag == elf::abi::DT_NEEDED)
+            .map(|(i, d)| Ok((i, usize::try_from(d.d_val()).context(IntConversionSnafu)?)))
+            .collect::<Result<_>>()?;
+
+        let mut matching_entries: Vec<(usize, usize)> = Vec::new();
+        for (dyn_entry_position, dynstr_index) in needed_entries {
+            if self.elf.dynstr_at(dynstr_index).context(SparseElfSnafu)? == old_library {
+                matching_entries.push((dyn_entry_position, dynstr_index));
+            }
+        }
+
+        if matching_entries.is_empty() {
+            return Err(Error::NeededNotFound {
+                library: old_library.to_string(),
+            });
+        }
+
+        let mut dynstr_indices: Vec<usize> = matching_entries.iter().map(|(_, i)| *i).collect();
+        dynstr_indices.sort_unstable();
+        dynstr_indices.dedup();
+
+        let mut suffix_referenced = false;
+        for dynstr_index in dynstr_indices.iter() {
+            suffix_referenced |= self.dynstr_suffix_referenced(*dynstr_index, old_library.len())?;
+        }
 
-        patch.data[..dyn_d_tag_data.len()].copy_from_slice(dyn_d_tag_data.bytes());
-        patch.data[dyn_d_tag_data.len()..].copy_from_slice(dyn_d_un_data.bytes());
+        if new_library.len() > old_library.len() || suffix_referenced {
+            let dynstr_entry_offset = self.sacrifice_dynstr_entry(new_library)? as u64;
+            for (dyn_entry_position, _) in matching_entries.iter() {
+                self.write_dynamic_entry(
+                    *dyn_entry_position,
+                    elf::abi::DT_NEEDED,
+                    dynstr_entry_offset,
+                )?;
+            }
+            return Ok(matching_entries.len());
+        }
+
+        for dynstr_index in dynstr_indices {
+            self.overwrite_dynstr_entry(dynstr_index, old_library.len(), new_library)?;
+        }
+
+        Ok(matching_entries.len())
+    }
+
+    /// Whether another string starts inside the .dynstr entry at `dynstr_index` with `len` bytes,
+    /// when the linker merged it into the entry as a suffix. Overwriting the entry in place would
+    /// change that string too.
+    fn dynstr_suffix_referenced(&mut self, dynstr_index: usize, len: usize) -> Result<bool> {
+        let entry_end = dynstr_index + len;
+
+        Ok(self
+            .elf
+            .dynstr_references()
+            .context(SparseElfSnafu)?
+            .iter()
+            .any(|r| *r > dynstr_index && *r <= entry_end))
+    }
+
+    /// Writes `new_string` over the .dynstr entry at `dynstr_index` with `len` bytes, clearing
+    /// the rest of it. Check [`Patcher::dynstr_suffix_referenced`] first.
+    fn overwrite_dynstr_entry(
+        &mut self,
+        dynstr_index: usize,
+        len: usize,
+        new_string: &str,
+    ) -> Result<()> {
+        let dynstr_target_offset =
+            section_file_offset(self.elf.shdr_dynstr.sh_offset, dynstr_index)?;
+
+        let patch = self.add_patch(dynstr_target_offset, len + 1);
+        patch.data[..new_string.len()].copy_from_slice(new_string.as_bytes());
+
+        Ok(())
+    }
+
+    /// Overwrites `d_val` of the first .dynamic entry with `d_tag`, keeping its tag.
+    ///
+    /// Useful to update entries like DT_STRSZ or to repoint a string entry.
+    pub fn set_dynamic_value(&mut self, d_tag: i64, d_val: u64) -> Result<()> {
+        self.operation = Some("dynamic value");
+
+        self.write_dynamic_value(d_tag, d_val)
+    }
+
+    fn write_dynamic_value(&mut self, d_tag: i64, d_val: u64) -> Result<()> {
+        let dyn_entry_position = self
+            .elf
+            .find_dynamic(d_tag)
+            .context(SparseElfSnafu)?
+            .ok_or(Error::NoDynamicEntry { d_tag })?;
+
+        let (d_val_offset, d_val_data) = self
+            .serializer
+            .bytes_from_dyn_value(d_val)
+            .context(SerializingSnafu)?;
+
+        let offset = self
+            .dynamic_entry_offset(dyn_entry_position)?
+            .checked_add(d_val_offset)
+            .ok_or(Error::IntegerOverflow)?;
+
+        self.patches.push(Patch {
+            offset,
+            data: d_val_data,
+            label: self.operation,
+        });
+
+        Ok(())
+    }
+
+    /// File offset of the .dynamic entry at `dyn_entry_position`
+    fn dynamic_entry_offset(&self, dyn_entry_position: usize) -> Result<usize> {
+        let dynamic_sh_offset =
+            usize::try_from(self.elf.shdr_dynamic.sh_offset).context(IntConversionSnafu)?;
+
+        dyn_entry_position
+            .checked_mul(self.serializer.dyn_entry_size())
+            .and_then(|dyn_table_offset| dynamic_sh_offset.checked_add(dyn_table_offset))
+            .ok_or(Error::IntegerOverflow)
+    }
+
+    fn write_dynamic_entry(
+        &mut self,
+        dyn_entry_position: usize,
+        d_tag: i64,
+        d_val: u64,
+    ) -> Result<()> {
+        let dyn_entry_offset = self.dynamic_entry_offset(dyn_entry_position)?;
+
+        let dyn_entry_data = self
+            .serializer
+            .bytes_from_dyn_entry(d_tag, d_val)
+            .context(SerializingSnafu)?;
+
+        self.patches.push(Patch {
+            offset: dyn_entry_offset,
+            data: dyn_entry_data,
+            label: self.operation,
+        });
 
         Ok(())
     }
 }
+
+#[test]
+fn test_overlapping_patches() {
+    let patches = vec![
+        Patch {
+            offset: 0x10,
+            data: vec![0; 8],
+            label: None,
+        },
+        Patch {
+            offset: 0x17,
+            data: vec![0; 4],
+            label: None,
+        },
+    ];
+
+    assert!(matches!(
+        check_overlapping_patches(&patches),
+        Err(Error::OverlappingPatches {
+            a_offset: 0x10,
+            b_offset: 0x17
+        })
+    ));
+}
+
+#[test]
+fn test_adjacent_patches() -> Result<()> {
+    let patches = vec![
+        Patch {
+            offset: 0x10,
+            data: vec![0; 8],
+            label: None,
+        },
+        Patch {
+            offset: 0x18,
+            data: vec![0; 4],
+            label: None,
+        },
+    ];
+
+    check_overlapping_patches(&patches)
+}
+
+#[test]
+fn test_apply_to() -> Result<()> {
+    let patches = [
+        Patch {
+            offset: 2,
+            data: vec![0xaa, 0xbb],
+            label: None,
+        },
+        Patch {
+            offset: 6,
+            data: vec![0xcc],
+            label: None,
+        },
+    ];
+
+    let mut target = io::Cursor::new(vec![0; 8]);
+    for patch in patches.iter() {
+        patch.apply_to(&mut target)?;
+    }
+    assert_eq!(target.into_inner(), [0, 0, 0xaa, 0xbb, 0, 0, 0xcc, 0]);
+
+    // Writing past the end extends the target, like it would a file
+    let mut target = io::Cursor::new(vec![0; 2]);
+    patches[0].apply_to(&mut target)?;
+    assert_eq!(target.into_inner(), [0, 0, 0xaa, 0xbb]);
+
+    Ok(())
+}
+
+#[test]
+fn test_append_to_search_path() {
+    assert_eq!(
+        append_to_search_path("/a:/b", "/c").as_deref(),
+        Some("/a:/b:/c")
+    );
+    assert_eq!(append_to_search_path("", "/c").as_deref(), Some("/c"));
+    assert_eq!(
+        append_to_search_path("/a", "/b:/a:/c").as_deref(),
+        Some("/a:/b:/c")
+    );
+    assert_eq!(append_to_search_path("/a:/b", "/b"), None);
+}
+
+#[test]
+fn test_normalize_runpath() {
+    assert_eq!(normalize_runpath("a::b:"), "a:b");
+    assert_eq!(normalize_runpath("/a,/b:/a,"), "/a:/b");
+    assert_eq!(normalize_runpath("$ORIGIN/../lib"), "$ORIGIN/../lib");
+    assert_eq!(normalize_runpath(":,"), "");
+}
+
+#[test]
+fn test_section_file_offset() {
+    assert_eq!(section_file_offset(0x120, 0x10).unwrap(), 0x130);
+    assert!(matches!(
+        section_file_offset(usize::MAX as u64 - 4, 0x10),
+        Err(Error::IntegerOverflow)
+    ));
+}
+
+#[test]
+fn test_suspicious_values() {
+    assert_eq!(runpath_problem("/a:$ORIGIN/../lib"), None);
+    assert!(runpath_problem("/a::/b").is_some());
+    assert!(runpath_problem("/a:").is_some());
+    assert_eq!(runpath_problem("${ORIGIN}/$LIB:/opt/$PLATFORM"), None);
+    assert!(runpath_problem("$ORIGN/../lib").is_some());
+    assert!(runpath_problem("$ORIGINAL").is_some());
+    assert!(runpath_problem("${ORIGIN/lib").is_some());
+    assert!(runpath_problem("/home/$USER/lib").is_some());
+    assert_eq!(interpreter_path_problem("/lib/ld.so"), None);
+    assert!(interpreter_path_problem("lib/ld.so").is_some());
+}
@@ -1,57 +1,839 @@
 use crate::opts::Opts;
-use crate::patch::{self, Patcher};
-use crate::sparse_elf;
+use crate::patch::{self, Patcher, RpathKind};
+use crate::profile::{self, Profile};
+use crate::report::Reporter;
+use crate::sparse_elf::{self, SparseElf};
 
-use colored::Colorize;
+use log::{debug, info, warn};
 use snafu::prelude::*;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Failed to write binary: {}", source))]
     WriteElf { source: std::io::Error },
 
+    #[snafu(display("Failed to create backup {}: {}", file_path, source))]
+    BackupElf {
+        file_path: String,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Failed to patch elf: {}", source))]
     PatchElf { source: patch::Error },
 
     #[snafu(display("{}", source))]
     SparseElf { source: sparse_elf::Error },
 
+    #[snafu(display("{}", source))]
+    Profile { source: profile::Error },
+
     #[snafu(display("Failed to get .dynamic section data"))]
     NoDynamicSection,
 
-    #[snafu(display("DT_RUNPATH is already set, overwriting it is not supported yet"))]
-    RunpathAlreadySet,
+    #[snafu(display(
+        "Verification failed, the patched {} is {:?} instead of {:?}",
+        what,
+        found,
+        expected
+    ))]
+    VerificationFailed {
+        what: String,
+        expected: Option<String>,
+        found: Option<String>,
+    },
+
+    #[snafu(display("Failed to buffer the binary from stdin: {}", source))]
+    BufferStdin { source: std::io::Error },
+
+    #[snafu(display("Failed to buffer {} for writing it to stdout: {}", file_path, source))]
+    BufferElf {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("{} can not be used when reading the binary from stdin", option))]
+    UnsupportedWithStdin { option: String },
+
+    #[snafu(display("{} can not be used when writing the binary to stdout", option))]
+    UnsupportedWithStdout { option: String },
+
+    #[snafu(display(
+        "{} can not be combined with writing the patched binary to stdout",
+        option
+    ))]
+    StdoutConflict { option: String },
+
+    #[snafu(display("{} is already set, use --force to overwrite it", kind))]
+    RunpathAlreadySet { kind: RpathKind },
+
+    #[snafu(display(
+        "The binary has {} runpath entries, use --consolidate-runpath to replace them with one",
+        count
+    ))]
+    MultipleRunpaths { count: usize },
+
+    #[snafu(display("Failed to resolve the location of {}: {}", file_path, source))]
+    CanonicalizeBin {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The runpath directory {} has to be absolute", dir))]
+    RelativeRunpathDir { dir: String },
+
+    #[snafu(display("Failed to read the interpreter of {}: {}", file_path, source))]
+    ReferenceInterpreter {
+        file_path: String,
+        source: sparse_elf::Error,
+    },
+
+    #[snafu(display("{} has an empty interpreter path", file_path))]
+    EmptyReferenceInterpreter { file_path: String },
+
+    #[snafu(display("Can't patch {} in place: {}", file_path, reason))]
+    NotWritable { file_path: String, reason: String },
+
+    #[snafu(display("The path {} is not valid UTF-8", path))]
+    NonUtf8Path { path: String },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-pub fn run(opts: Opts) -> Result<()> {
-    let mut patcher = Patcher::new(&opts.bin).context(PatchElfSnafu)?;
+/// What `run` did to the binary
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The requested changes were written
+    Patched,
+    /// Only queries were requested, nothing was written
+    Queried,
+    /// Nothing was requested or everything was already in place
+    NothingToDo,
+    /// The patches were planned but not written, see `--dry-run`
+    Planned,
+    /// The patches were planned and checked but not written, see `--check`
+    Checked,
+    /// The binary is for another machine than `--machine-filter` asks for
+    Skipped,
+}
 
-    if let Some(runpath) = opts.set_runpath {
-        if patcher
-            .elf
-            .dynamic_contains(elf::abi::DT_RUNPATH)
-            .context(SparseElfSnafu)?
-        {
-            return Err(Error::RunpathAlreadySet);
+/// What `run_batch` did to each of the binaries
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub succeeded: Vec<(PathBuf, Status)>,
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+impl BatchSummary {
+    pub fn all_succeeded(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub fn all_failed(&self) -> bool {
+        self.succeeded.is_empty() && !self.failed.is_empty()
+    }
+}
+
+/// The `--bin` value that reads the binary from stdin and writes the result to stdout
+pub const STDIN_PATH: &str = "-";
+
+/// The `--output` value that writes the result to stdout and leaves the binary untouched
+pub const STDOUT_PATH: &str = "-";
+
+pub fn run(opts: Opts) -> Result<Status> {
+    let opts = with_profile(opts)?;
+
+    if opts.verbose {
+        opts.print();
+    }
+
+    let from_stdin = opts.bin.as_os_str() == STDIN_PATH;
+    let output_to_stdout = opts
+        .output
+        .as_ref()
+        .is_some_and(|output| output.as_os_str() == STDOUT_PATH);
+    // The result goes to stdout if it is asked to, or if it has nowhere else to go
+    let to_stdout = output_to_stdout || (from_stdin && opts.output.is_none());
+    // A buffered copy is patched in place and written to stdout
+    let opts = if output_to_stdout {
+        Opts {
+            output: None,
+            ..opts
         }
+    } else {
+        opts
+    };
 
-        patcher.set_runpath(&runpath).context(PatchElfSnafu)?;
+    // Fail before parsing if the binary is going to be patched in place but can't be written
+    if !from_stdin
+        && !to_stdout
+        && opts.output.is_none()
+        && !opts.atomic
+        && !opts.dry_run
+        && opts.has_changes()
+    {
+        check_writable(&opts.bin)?;
     }
 
-    if let Some(interpreter_path) = opts.set_interpreter {
-        patcher
-            .set_interpreter_path(&interpreter_path)
-            .context(PatchElfSnafu)?;
+    let mut patcher = if from_stdin {
+        check_stdin_options(&opts)?;
+        let file = buffer(io::stdin().lock()).context(BufferStdinSnafu)?;
+        Patcher::from_file(file, "<stdin>", opts.allow_any_type)
+    } else if to_stdout {
+        check_stdout_options(&opts)?;
+        let file = File::open(&opts.bin)
+            .and_then(buffer)
+            .context(BufferElfSnafu {
+                file_path: opts.bin.to_string_lossy(),
+            })?;
+        Patcher::from_file(file, &opts.bin.to_string_lossy(), opts.allow_any_type)
+    } else {
+        open_patcher(&opts)
+    }
+    .context(PatchElfSnafu)?;
+
+    if let Some(machine) = opts.machine_filter {
+        let e_machine = patcher.elf.e_machine();
+        if e_machine != machine {
+            info!(
+                "Skipping {}, it is {} instead of {}",
+                opts.bin.to_string_lossy(),
+                elf::to_str::e_machine_to_string(e_machine),
+                elf::to_str::e_machine_to_string(machine)
+            );
+
+            // Pass the binary through unchanged, like when there is nothing to do
+            if to_stdout && !opts.dry_run && !opts.check {
+                write_to_stdout(patcher.elf.file())?;
+            }
+            return Ok(Status::Skipped);
+        }
+    }
+
+    let has_queries = opts.has_queries();
+
+    let mut reporter = Reporter::new(opts.format);
+
+    if opts.print_runpath || opts.print_rpath_entries {
+        let runpath = patcher.elf.runpath().context(SparseElfSnafu)?;
+        let count = patcher.elf.runpaths().context(SparseElfSnafu)?.len();
+        if count > 1 {
+            warn!(
+                "The binary has {} runpath entries, printing the first",
+                count
+            );
+        }
+        if opts.print_runpath {
+            reporter.runpath(runpath.as_deref());
+        }
+        if opts.print_rpath_entries {
+            reporter.runpath_entries(runpath.as_deref());
+        }
+    }
+
+    if opts.print_interpreter {
+        let interpreter = patcher.elf.interpreter().context(SparseElfSnafu)?;
+        reporter.interpreter(&interpreter);
+    }
+
+    if opts.print_needed {
+        let needed = patcher.elf.needed().context(SparseElfSnafu)?;
+        reporter.needed(&needed);
+    }
+
+    if opts.print_soname {
+        let soname = patcher.elf.soname().context(SparseElfSnafu)?;
+        reporter.soname(soname.as_deref());
+    }
+
+    if opts.print_osabi {
+        reporter.osabi(patcher.elf.osabi());
+    }
+
+    if opts.print_flags {
+        let flags = patcher.elf.flags().context(SparseElfSnafu)?;
+        reporter.flags(&flags);
+    }
+
+    if opts.print_pie {
+        let pie = patcher.elf.is_pie().context(SparseElfSnafu)?;
+        reporter.pie(pie);
     }
 
+    if opts.print_dynamic_tags {
+        let dynamic_tags = patcher.elf.notable_dynamic_tags().context(SparseElfSnafu)?;
+        reporter.dynamic_tags(&dynamic_tags);
+    }
+
+    if opts.print_summary {
+        reporter.summary(&patcher.elf.summary());
+    }
+
+    if opts.dynamic_stats {
+        let stats = patcher.elf.dynamic_stats().context(SparseElfSnafu)?;
+        reporter.dynamic_stats(&stats);
+    }
+
+    if opts.list_candidates {
+        for symbol in opts.sacrifice_symbol.iter() {
+            patcher.add_sacrifice_symbol(symbol);
+        }
+        patcher.set_ignore_candidate_safety(opts.ignore_candidate_safety);
+
+        let candidates = patcher.list_candidates().context(PatchElfSnafu)?;
+        reporter.candidates(&candidates);
+    }
+
+    plan_patches(&mut patcher, &opts)?;
+    reporter.sacrifices(patcher.sacrifices());
+
     if patcher.is_empty() {
-        println!("{}", "Nothing to do".yellow());
-        return Ok(());
+        reporter.finish();
+
+        if has_queries {
+            return Ok(Status::Queried);
+        }
+
+        // Pass the binary through, so a pipeline keeps working
+        if to_stdout && !opts.dry_run && !opts.check {
+            write_to_stdout(patcher.elf.file())?;
+        }
+
+        // The output is expected to exist afterwards, even if it is an unchanged copy
+        if opts.output.is_some() && !opts.dry_run && !opts.check {
+            patcher.apply().context(PatchElfSnafu)?;
+        }
+
+        info!("Nothing to do");
+        return Ok(Status::NothingToDo);
+    }
+
+    if opts.check {
+        patcher.check().context(PatchElfSnafu)?;
+        reporter.finish();
+        info!("The requested changes can be applied");
+        return Ok(Status::Checked);
+    }
+
+    // stdout is taken by the binary, unless nothing is written
+    if to_stdout && !opts.dry_run && (has_queries || opts.show_diff || opts.json_plan) {
+        return Err(Error::StdoutConflict {
+            option: if opts.show_diff {
+                "--show-diff"
+            } else if opts.json_plan {
+                "--json-plan"
+            } else {
+                "A print option"
+            }
+            .to_string(),
+        });
+    }
+
+    if opts.show_diff {
+        let diff = patcher.diff().context(PatchElfSnafu)?;
+        reporter.diff(&diff);
+    }
+
+    if opts.json_plan {
+        reporter.plan(&patcher.plan());
+    }
+
+    if opts.dry_run {
+        reporter.finish();
+        info!(
+            "Dry run, {} patches planned and not written",
+            patcher.planned_patches().len()
+        );
+        return Ok(Status::Planned);
+    }
+
+    if opts.backup {
+        let mut backup_path = opts.bin.clone().into_os_string();
+        backup_path.push(&opts.backup_suffix);
+
+        std::fs::copy(&opts.bin, &backup_path).context(BackupElfSnafu {
+            file_path: backup_path.to_string_lossy(),
+        })?;
     }
 
     patcher.apply().context(PatchElfSnafu)?;
+    info!("{}", patcher.summary());
+
+    if to_stdout {
+        write_to_stdout(patcher.elf.file())?;
+    }
+
+    if opts.verify {
+        verify(&opts)?;
+    }
+
+    reporter.finish();
+
+    Ok(Status::Patched)
+}
+
+/// Rejects the options that need the binary to have a path. With --output only the backup is
+/// impossible, as the output is a regular file
+fn check_stdin_options(opts: &Opts) -> Result<()> {
+    match path_option(opts) {
+        Some(option) => Err(Error::UnsupportedWithStdin { option }),
+        None => Ok(()),
+    }
+}
+
+/// Rejects the options that need the patched binary to have a path, with `--output -`
+fn check_stdout_options(opts: &Opts) -> Result<()> {
+    match path_option(opts) {
+        Some(option) => Err(Error::UnsupportedWithStdout { option }),
+        None => Ok(()),
+    }
+}
+
+/// The first given option that needs a path to the binary or the output
+fn path_option(opts: &Opts) -> Option<String> {
+    let without_output = opts.output.is_none();
+    let path_options = [
+        (opts.atomic && without_output, "--atomic"),
+        (opts.backup, "--backup"),
+        (
+            opts.relative_runpath.is_some() && without_output,
+            "--relative-runpath",
+        ),
+        (opts.verify && without_output, "--verify"),
+        (
+            opts.preserve_timestamps && without_output,
+            "--preserve-timestamps",
+        ),
+    ];
+
+    path_options
+        .iter()
+        .find(|(set, _)| *set)
+        .map(|(_, option)| option.to_string())
+}
+
+/// Opens `file_path` for writing without changing it, to tell why it can't be patched
+fn check_writable(file_path: &Path) -> Result<()> {
+    let error = match OpenOptions::new().write(true).open(file_path) {
+        Ok(_) => return Ok(()),
+        Err(error) => error,
+    };
+
+    let reason = match error.kind() {
+        io::ErrorKind::ReadOnlyFilesystem => {
+            "it is on a read-only filesystem, use --output to write a patched copy".to_string()
+        }
+        io::ErrorKind::PermissionDenied => {
+            "permission denied, check the file mode and owner or use --output".to_string()
+        }
+        _ => error.to_string(),
+    };
+
+    Err(Error::NotWritable {
+        file_path: file_path.to_string_lossy().to_string(),
+        reason,
+    })
+}
+
+/// Copies `source` to an anonymous temporary file, as parsing the elf needs to seek and patching
+/// it needs to write
+fn buffer(mut source: impl Read) -> io::Result<File> {
+    let mut file = tempfile::tempfile()?;
+    io::copy(&mut source, &mut file)?;
+
+    Ok(file)
+}
+
+fn write_to_stdout(mut file: &File) -> Result<()> {
+    file.seek(SeekFrom::Start(0)).context(WriteElfSnafu)?;
+
+    let mut stdout = io::stdout().lock();
+    io::copy(&mut file, &mut stdout).context(WriteElfSnafu)?;
+    stdout.flush().context(WriteElfSnafu)
+}
+
+/// Opens the binary and plans all patches requested by `opts`, without writing them.
+///
+/// The returned patcher can be inspected with `Patcher::planned_patches` and written with
+/// `Patcher::apply`.
+pub fn plan(opts: &Opts) -> Result<Patcher> {
+    let opts = with_profile(opts.clone())?;
+
+    let mut patcher = open_patcher(&opts).context(PatchElfSnafu)?;
+    plan_patches(&mut patcher, &opts)?;
+
+    Ok(patcher)
+}
+
+/// Opens `--bin`, locked unless --no-lock is given
+fn open_patcher(opts: &Opts) -> Result<Patcher, patch::Error> {
+    if opts.no_lock {
+        Patcher::open_unlocked(&opts.bin, opts.allow_any_type)
+    } else {
+        Patcher::open(&opts.bin, opts.allow_any_type)
+    }
+}
+
+/// Runs `opts` on `--bin` and every binary in `more_bins`. A failing binary does not stop the
+/// ones after it
+pub fn run_batch(opts: Opts) -> BatchSummary {
+    let bins = std::iter::once(opts.bin.clone()).chain(opts.more_bins.clone());
+    let mut summary = BatchSummary::default();
+
+    for bin in bins {
+        info!("Processing {}", bin.to_string_lossy());
+        let bin_opts = Opts {
+            bin: bin.clone(),
+            more_bins: Vec::new(),
+            ..opts.clone()
+        };
+
+        match run(bin_opts) {
+            Ok(status) => summary.succeeded.push((bin, status)),
+            Err(err) => summary.failed.push((bin, err)),
+        }
+    }
+
+    summary
+}
+
+/// Fills the options that are not given on the command line from the --profile, if any
+fn with_profile(opts: Opts) -> Result<Opts> {
+    let Some(profile_path) = opts.profile.clone() else {
+        return Ok(opts);
+    };
+
+    Profile::load(&profile_path)
+        .and_then(|profile| profile.merge_into(opts))
+        .context(ProfileSnafu)
+}
+
+fn plan_patches(patcher: &mut Patcher, opts: &Opts) -> Result<()> {
+    for symbol in opts.sacrifice_symbol.iter() {
+        patcher.add_sacrifice_symbol(symbol);
+    }
+
+    if let Some(dynstr_offset) = opts.sacrifice_offset {
+        patcher.set_sacrifice_offset(dynstr_offset);
+    }
+
+    if let Some(class) = opts.force_class {
+        patcher.set_force_class(class);
+    }
+    if let Some(endianness) = opts.force_endian {
+        patcher.set_force_endianness(endianness);
+    }
+    patcher.set_preferred_candidate(opts.prefer_candidate.clone());
+    patcher.set_allow_candidate_fallback(opts.allow_candidate_fallback);
+    patcher.set_safe(opts.safe);
+    patcher.set_ignore_candidate_safety(opts.ignore_candidate_safety);
+    patcher.set_strict(opts.strict);
+    patcher.set_dynamic_strategy(opts.dynamic_strategy);
+    patcher.set_atomic(opts.atomic);
+    patcher.set_keep_interpreter_tail(opts.keep_interpreter_tail);
+    patcher.set_allow_relocate_interp(opts.allow_relocate_interp);
+    patcher.set_preserve_timestamps(opts.preserve_timestamps);
+    patcher.set_output(opts.output.clone());
+
+    let relative_runpath = relative_runpath(opts)?;
+    let runpath_request = match (opts.set_runpath.as_deref(), opts.set_rpath.as_deref()) {
+        (Some(runpath), _) => Some((runpath, RpathKind::Runpath)),
+        (None, Some(rpath)) => Some((rpath, RpathKind::Rpath)),
+        (None, None) => relative_runpath
+            .as_deref()
+            .map(|runpath| (runpath, RpathKind::Runpath)),
+    }
+    .map(|(runpath, kind)| (patch::normalize_runpath(runpath), kind));
+
+    // An empty runpath means clearing it, like --remove-runpath
+    let remove_runpath = opts.remove_runpath
+        || runpath_request
+            .as_ref()
+            .is_some_and(|(runpath, _)| runpath.is_empty());
+    let runpath_request = runpath_request.filter(|(runpath, _)| !runpath.is_empty());
+
+    if let Some((runpath, kind)) = runpath_request.as_ref().map(|(r, k)| (r.as_str(), *k)) {
+        // A binary should only carry one search path tag, so an existing entry of either kind is
+        // reused and converted to the requested kind.
+        let mut existing_kind = None;
+        for candidate_kind in [kind, kind.opposite()] {
+            if patcher
+                .elf
+                .dynamic_contains(candidate_kind.d_tag())
+                .context(SparseElfSnafu)?
+            {
+                existing_kind = Some(candidate_kind);
+                break;
+            }
+        }
+
+        let current_runpath = match existing_kind {
+            Some(existing_kind) => patcher
+                .elf
+                .dynamic_string(existing_kind.d_tag())
+                .context(SparseElfSnafu)?,
+            None => None,
+        };
+
+        match existing_kind {
+            // Re-running the same command must not sacrifice anything
+            Some(existing_kind)
+                if existing_kind == kind && current_runpath.as_deref() == Some(runpath) =>
+            {
+                debug!("{} is already {}", kind, runpath);
+                Ok(())
+            }
+            Some(existing_kind) if !opts.force => {
+                return Err(Error::RunpathAlreadySet {
+                    kind: existing_kind,
+                })
+            }
+            Some(existing_kind) => {
+                let count = patcher.elf.runpaths().context(SparseElfSnafu)?.len();
+                if count > 1 {
+                    if !opts.consolidate_runpath {
+                        return Err(Error::MultipleRunpaths { count });
+                    }
+                    patcher
+                        .remove_extra_runpaths(existing_kind)
+                        .context(PatchElfSnafu)?;
+                }
+                patcher.replace_runpath(runpath, existing_kind, kind)
+            }
+            None => patcher.set_runpath(runpath, kind).map(|_| ()),
+        }
+        .context(PatchElfSnafu)?;
+    }
+
+    if let Some(directories) = &opts.append_runpath {
+        patcher.append_runpath(directories).context(PatchElfSnafu)?;
+    }
+
+    if remove_runpath {
+        patcher.remove_runpath().context(PatchElfSnafu)?;
+    }
+
+    if opts.convert_rpath_to_runpath {
+        patcher.convert_rpath_to_runpath().context(PatchElfSnafu)?;
+    }
+
+    let needed = if opts.add_needed.is_empty() {
+        Vec::new()
+    } else {
+        patcher.elf.needed().context(SparseElfSnafu)?
+    };
+    for library in opts.add_needed.iter() {
+        if needed.contains(library) {
+            debug!("{} is already needed", library);
+            continue;
+        }
+        patcher.add_needed(library).context(PatchElfSnafu)?;
+    }
+
+    if let Some(library) = &opts.remove_needed {
+        patcher.remove_needed(library).context(PatchElfSnafu)?;
+    }
+
+    if let Some(libraries) = &opts.replace_needed {
+        let replaced = patcher
+            .replace_needed(&libraries[0], &libraries[1])
+            .context(PatchElfSnafu)?;
+        info!(
+            "Replaced {} DT_NEEDED entries of {} with {}",
+            replaced, libraries[0], libraries[1]
+        );
+    }
+
+    if let Some(soname) = &opts.set_soname {
+        if patcher.elf.soname().context(SparseElfSnafu)?.as_deref() == Some(soname.as_str()) {
+            debug!("The soname is already {}", soname);
+        } else if let Some(previous_soname) = patcher.set_soname(soname).context(PatchElfSnafu)? {
+            debug!("Replacing soname {} with {}", previous_soname, soname);
+        }
+    }
+
+    if let Some(osabi) = opts.set_osabi.filter(|osabi| *osabi != patcher.elf.osabi()) {
+        patcher.set_osabi(osabi);
+    }
+
+    if !opts.set_flag1.is_empty() || !opts.clear_flag1.is_empty() {
+        let set = opts.set_flag1.iter().fold(0, |bits, flag| bits | flag);
+        let clear = opts.clear_flag1.iter().fold(0, |bits, flag| bits | flag);
+        patcher.update_flags_1(set, clear).context(PatchElfSnafu)?;
+    }
+
+    if let Some(execstack) = opts.set_execstack {
+        patcher.set_execstack(execstack).context(PatchElfSnafu)?;
+    }
+
+    if let Some(interpreter_path) = &requested_interpreter(opts)? {
+        // A binary without .interp fails below, with a better message than here
+        if patcher.elf.interpreter().ok().as_ref() == Some(interpreter_path) {
+            debug!("The interpreter is already {}", interpreter_path);
+        } else if let Some(previous_interpreter_path) = patcher
+            .set_interpreter_path(interpreter_path)
+            .context(PatchElfSnafu)?
+        {
+            debug!(
+                "Replacing interpreter {} with {}",
+                previous_interpreter_path, interpreter_path
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The interpreter path given with --set-interpreter, or read from the --interpreter-from binary
+fn requested_interpreter(opts: &Opts) -> Result<Option<String>> {
+    let Some(reference_path) = &opts.interpreter_from else {
+        return Ok(opts.set_interpreter.clone());
+    };
+
+    let interpreter_path = SparseElf::new(reference_path)
+        .and_then(|mut reference| reference.interpreter())
+        .context(ReferenceInterpreterSnafu {
+            file_path: reference_path.to_string_lossy(),
+        })?;
+
+    if interpreter_path.is_empty() {
+        return Err(Error::EmptyReferenceInterpreter {
+            file_path: reference_path.to_string_lossy().to_string(),
+        });
+    }
+
+    Ok(Some(interpreter_path))
+}
+
+/// The runpath requested with --relative-runpath, relative to where the patched binary lives
+fn relative_runpath(opts: &Opts) -> Result<Option<String>> {
+    let Some(dir) = &opts.relative_runpath else {
+        return Ok(None);
+    };
+
+    let binary_path = opts.output.as_ref().unwrap_or(&opts.bin);
+    let canonical_path = match fs::canonicalize(binary_path) {
+        Ok(canonical_path) => canonical_path,
+        // The output is only created when patching
+        Err(_) => {
+            let parent = binary_path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            fs::canonicalize(parent)
+                .map(|parent| parent.join(binary_path.file_name().unwrap_or_default()))
+                .context(CanonicalizeBinSnafu {
+                    file_path: binary_path.to_string_lossy(),
+                })?
+        }
+    };
+
+    let origin_dir = canonical_path.parent().unwrap_or(Path::new("/"));
+    origin_relative_runpath(origin_dir, dir).map(Some)
+}
+
+/// Expresses the absolute `dir` relative to `origin_dir` as a runpath starting with $ORIGIN.
+///
+/// This is pure path math, `.` and `..` in `dir` are resolved lexically and symlinks are not
+/// followed. Whether `dir` is on the same filesystem as the binary is not checked.
+pub fn origin_relative_runpath(origin_dir: &Path, dir: &Path) -> Result<String> {
+    if !dir.is_absolute() {
+        return Err(Error::RelativeRunpathDir {
+            dir: dir.to_string_lossy().to_string(),
+        });
+    }
+
+    let origin_components = normalized_components(origin_dir);
+    let dir_components = normalized_components(dir);
+
+    let common = origin_components
+        .iter()
+        .zip(dir_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::from("$ORIGIN");
+    for _ in common..origin_components.len() {
+        relative.push("..");
+    }
+    for component in &dir_components[common..] {
+        relative.push(component);
+    }
+
+    relative
+        .into_os_string()
+        .into_string()
+        .map_err(|path| Error::NonUtf8Path {
+            path: path.to_string_lossy().to_string(),
+        })
+}
+
+/// Components of `path` with `.` dropped and `..` applied to the preceding directory
+fn normalized_components(path: &Path) -> Vec<Component<'_>> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(components.last(), Some(Component::Normal(_))) {
+                    components.pop();
+                }
+            }
+            component => components.push(component),
+        }
+    }
+
+    components
+}
+
+/// Re-parses the patched elf and checks the runpath, soname and interpreter that were requested
+fn verify(opts: &Opts) -> Result<()> {
+    let path = opts.output.as_ref().unwrap_or(&opts.bin);
+    let mut elf = if opts.allow_any_type {
+        SparseElf::open_any_type(path, false)
+    } else {
+        SparseElf::new(path)
+    }
+    .context(SparseElfSnafu)?;
+
+    let expected_runpath = match (&opts.set_runpath, &opts.set_rpath) {
+        (Some(runpath), _) | (None, Some(runpath)) => {
+            let runpath = patch::normalize_runpath(runpath);
+            Some((!runpath.is_empty()).then_some(runpath))
+        }
+        (None, None) if opts.remove_runpath => Some(None),
+        (None, None) => relative_runpath(opts)?.map(Some),
+    };
+
+    if let Some(expected) = expected_runpath {
+        check_verified("runpath", expected, elf.runpath().context(SparseElfSnafu)?)?;
+    }
+
+    if let Some(soname) = &opts.set_soname {
+        let found = elf.soname().context(SparseElfSnafu)?;
+        check_verified("soname", Some(soname.clone()), found)?;
+    }
+
+    if let Some(interpreter_path) = &requested_interpreter(opts)? {
+        let found = elf.interpreter().context(SparseElfSnafu)?;
+        check_verified("interpreter", Some(interpreter_path.clone()), Some(found))?;
+    }
+
+    Ok(())
+}
+
+fn check_verified(what: &str, expected: Option<String>, found: Option<String>) -> Result<()> {
+    if expected != found {
+        return Err(Error::VerificationFailed {
+            what: what.to_string(),
+            expected,
+            found,
+        });
+    }
 
     Ok(())
 }
@@ -0,0 +1,226 @@
+use crate::opts::{parse_class, parse_endian, parse_flag_1, parse_machine, parse_osabi, Opts};
+use crate::patch::DynamicStrategy;
+
+use serde::Deserialize;
+use snafu::prelude::*;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Failed to read profile {}: {}", file_path, source))]
+    ReadProfile {
+        file_path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to parse profile {}: {}", file_path, source))]
+    ParseProfile {
+        file_path: String,
+        source: toml::de::Error,
+    },
+
+    #[snafu(display("Invalid value for {} in the profile: {}", key, message))]
+    InvalidValue { key: String, message: String },
+
+    #[snafu(display(
+        "The profile combines --{} with --{}, which exclude each other",
+        first,
+        second
+    ))]
+    ConflictingOptions {
+        first: &'static str,
+        second: &'static str,
+    },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Options read from a TOML profile, keyed by the long option names with underscores
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    set_runpath: Option<String>,
+    set_rpath: Option<String>,
+    append_runpath: Option<String>,
+    relative_runpath: Option<PathBuf>,
+    remove_runpath: Option<bool>,
+    consolidate_runpath: Option<bool>,
+    convert_rpath_to_runpath: Option<bool>,
+    #[serde(default)]
+    add_needed: Vec<String>,
+    remove_needed: Option<String>,
+    replace_needed: Option<[String; 2]>,
+    set_soname: Option<String>,
+    set_interpreter: Option<String>,
+    set_osabi: Option<String>,
+    set_execstack: Option<bool>,
+    #[serde(default)]
+    set_flag1: Vec<String>,
+    #[serde(default)]
+    clear_flag1: Vec<String>,
+    keep_interpreter_tail: Option<bool>,
+    allow_relocate_interp: Option<bool>,
+    #[serde(default)]
+    sacrifice_symbol: Vec<String>,
+    prefer_candidate: Option<String>,
+    allow_candidate_fallback: Option<bool>,
+    safe: Option<bool>,
+    ignore_candidate_safety: Option<bool>,
+    strict: Option<bool>,
+    dynamic_strategy: Option<String>,
+    force_class: Option<String>,
+    force_endian: Option<String>,
+    machine_filter: Option<String>,
+    atomic: Option<bool>,
+    preserve_timestamps: Option<bool>,
+    no_lock: Option<bool>,
+    verify: Option<bool>,
+    force: Option<bool>,
+}
+
+impl Profile {
+    pub fn load(file_path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(file_path).context(ReadProfileSnafu {
+            file_path: file_path.to_string_lossy(),
+        })?;
+
+        toml::from_str(&content).context(ParseProfileSnafu {
+            file_path: file_path.to_string_lossy(),
+        })
+    }
+
+    /// Fills the options that `opts` leaves unset from the profile.
+    ///
+    /// Flags can only be turned on. The runpath options exclude each other, so the profile's
+    /// are ignored if any of them is given on the command line. Other options that exclude each
+    /// other are an error, whether both come from the profile or one from the command line.
+    pub fn merge_into(self, mut opts: Opts) -> Result<Opts> {
+        let cli_sets_runpath = opts.set_runpath.is_some()
+            || opts.set_rpath.is_some()
+            || opts.append_runpath.is_some()
+            || opts.relative_runpath.is_some()
+            || opts.remove_runpath
+            || opts.convert_rpath_to_runpath;
+        if !cli_sets_runpath {
+            opts.set_runpath = self.set_runpath;
+            opts.set_rpath = self.set_rpath;
+            opts.append_runpath = self.append_runpath;
+            opts.relative_runpath = self.relative_runpath;
+            opts.remove_runpath = self.remove_runpath.unwrap_or(false);
+            opts.convert_rpath_to_runpath = self.convert_rpath_to_runpath.unwrap_or(false);
+        }
+
+        if opts.add_needed.is_empty() {
+            opts.add_needed = self.add_needed;
+        }
+        opts.remove_needed = opts.remove_needed.or(self.remove_needed);
+        opts.replace_needed = opts
+            .replace_needed
+            .or(self.replace_needed.map(|libraries| libraries.to_vec()));
+        opts.set_soname = opts.set_soname.or(self.set_soname);
+        if opts.interpreter_from.is_none() {
+            opts.set_interpreter = opts.set_interpreter.or(self.set_interpreter);
+        }
+        opts.set_execstack = opts.set_execstack.or(self.set_execstack);
+
+        if opts.set_osabi.is_none() {
+            opts.set_osabi = self
+                .set_osabi
+                .map(|osabi| parse_osabi(&osabi))
+                .transpose()
+                .map_err(|message| Error::InvalidValue {
+                    key: "set_osabi".to_string(),
+                    message,
+                })?;
+        }
+
+        if opts.set_flag1.is_empty() {
+            opts.set_flag1 = parse_flags_1("set_flag1", &self.set_flag1)?;
+        }
+        if opts.clear_flag1.is_empty() {
+            opts.clear_flag1 = parse_flags_1("clear_flag1", &self.clear_flag1)?;
+        }
+
+        if opts.sacrifice_symbol.is_empty() {
+            opts.sacrifice_symbol = self.sacrifice_symbol;
+        }
+
+        opts.prefer_candidate = opts.prefer_candidate.or(self.prefer_candidate);
+
+        // The command line default can't be told apart from an explicit auto
+        if opts.dynamic_strategy == DynamicStrategy::default() {
+            if let Some(dynamic_strategy) = self.dynamic_strategy {
+                opts.dynamic_strategy =
+                    dynamic_strategy
+                        .parse()
+                        .map_err(|message| Error::InvalidValue {
+                            key: "dynamic_strategy".to_string(),
+                            message,
+                        })?;
+            }
+        }
+
+        if opts.force_class.is_none() {
+            opts.force_class = self
+                .force_class
+                .map(|class| parse_class(&class))
+                .transpose()
+                .map_err(|message| Error::InvalidValue {
+                    key: "force_class".to_string(),
+                    message,
+                })?;
+        }
+
+        if opts.force_endian.is_none() {
+            opts.force_endian = self
+                .force_endian
+                .map(|endian| parse_endian(&endian))
+                .transpose()
+                .map_err(|message| Error::InvalidValue {
+                    key: "force_endian".to_string(),
+                    message,
+                })?;
+        }
+
+        if opts.machine_filter.is_none() {
+            opts.machine_filter = self
+                .machine_filter
+                .map(|machine| parse_machine(&machine))
+                .transpose()
+                .map_err(|message| Error::InvalidValue {
+                    key: "machine_filter".to_string(),
+                    message,
+                })?;
+        }
+
+        opts.keep_interpreter_tail |= self.keep_interpreter_tail.unwrap_or(false);
+        opts.allow_relocate_interp |= self.allow_relocate_interp.unwrap_or(false);
+        opts.consolidate_runpath |= self.consolidate_runpath.unwrap_or(false);
+        opts.allow_candidate_fallback |= self.allow_candidate_fallback.unwrap_or(false);
+        opts.safe |= self.safe.unwrap_or(false);
+        opts.ignore_candidate_safety |= self.ignore_candidate_safety.unwrap_or(false);
+        opts.strict |= self.strict.unwrap_or(false);
+        opts.atomic |= self.atomic.unwrap_or(false);
+        opts.preserve_timestamps |= self.preserve_timestamps.unwrap_or(false);
+        opts.no_lock |= self.no_lock.unwrap_or(false);
+        opts.verify |= self.verify.unwrap_or(false);
+        opts.force |= self.force.unwrap_or(false);
+
+        if let Some((first, second)) = opts.conflicting_options() {
+            return Err(Error::ConflictingOptions { first, second });
+        }
+
+        Ok(opts)
+    }
+}
+
+fn parse_flags_1(key: &str, names: &[String]) -> Result<Vec<u64>> {
+    names
+        .iter()
+        .map(|name| parse_flag_1(name))
+        .collect::<Result<_, String>>()
+        .map_err(|message| Error::InvalidValue {
+            key: key.to_string(),
+            message,
+        })
+}
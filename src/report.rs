@@ -0,0 +1,348 @@
+use crate::patch::{DynstrCandidate, PatchDiff, PlannedPatch, SacrificeReport};
+use crate::sparse_elf::{self, flag_names, DynamicFlags, DynamicStats, ElfSummary};
+
+use colored::{ColoredString, Colorize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown output format {}", s)),
+        }
+    }
+}
+
+/// Prints query results and messages in the requested output format.
+///
+/// Only requested data goes to stdout, messages about missing values go to stderr.
+///
+/// In json mode query results are collected and printed as a single object by `finish`.
+pub struct Reporter {
+    format: OutputFormat,
+    json: Map<String, Value>,
+}
+
+impl Reporter {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            json: Map::new(),
+        }
+    }
+
+    pub fn runpath(&mut self, runpath: Option<&str>) {
+        self.optional_string("runpath", runpath, "No runpath set");
+    }
+
+    /// The runpath split on ':', an empty array in json mode if there is none
+    pub fn runpath_entries(&mut self, runpath: Option<&str>) {
+        let entries: Vec<&str> = runpath.map(|r| r.split(':').collect()).unwrap_or_default();
+
+        match self.format {
+            OutputFormat::Human => {
+                if runpath.is_none() {
+                    eprintln!("{}", "No runpath set".yellow());
+                }
+                for entry in entries {
+                    println!("{}", entry);
+                }
+            }
+            OutputFormat::Json => {
+                self.json
+                    .insert("runpath_entries".to_string(), entries.into());
+            }
+        }
+    }
+
+    pub fn interpreter(&mut self, interpreter: &str) {
+        match self.format {
+            OutputFormat::Human => println!("{}", interpreter),
+            OutputFormat::Json => {
+                self.json
+                    .insert("interpreter".to_string(), interpreter.into());
+            }
+        }
+    }
+
+    pub fn needed(&mut self, needed: &[String]) {
+        match self.format {
+            OutputFormat::Human => {
+                for library in needed {
+                    println!("{}", library);
+                }
+            }
+            OutputFormat::Json => {
+                self.json.insert("needed".to_string(), needed.into());
+            }
+        }
+    }
+
+    pub fn soname(&mut self, soname: Option<&str>) {
+        self.optional_string("soname", soname, "No soname set");
+    }
+
+    /// The .dynstr entries that were overwritten. Human mode already warns while planning.
+    pub fn sacrifices(&mut self, sacrifices: &[SacrificeReport]) {
+        if self.format == OutputFormat::Json && !sacrifices.is_empty() {
+            let sacrifices: Vec<Value> = sacrifices
+                .iter()
+                .map(|sacrifice| {
+                    let mut entry = Map::new();
+                    entry.insert("symbol".to_string(), sacrifice.symbol.clone().into());
+                    entry.insert("dynstr_offset".to_string(), sacrifice.dynstr_offset.into());
+                    Value::Object(entry)
+                })
+                .collect();
+            self.json
+                .insert("sacrificed".to_string(), sacrifices.into());
+        }
+    }
+
+    /// Hexdump of the bytes that are about to be overwritten, next to their replacement
+    pub fn diff(&mut self, diff: &[PatchDiff]) {
+        match self.format {
+            OutputFormat::Human => {
+                for patch_diff in diff {
+                    println!("{}", format!("{:#010x}:", patch_diff.offset).cyan());
+                    print_hexdump(patch_diff.offset, &patch_diff.old, |l| l.red(), '-');
+                    print_hexdump(patch_diff.offset, &patch_diff.new, |l| l.green(), '+');
+                }
+            }
+            OutputFormat::Json => {
+                let patches: Vec<Value> = diff
+                    .iter()
+                    .map(|d| {
+                        let mut patch = Map::new();
+                        patch.insert("offset".to_string(), d.offset.into());
+                        patch.insert("old".to_string(), hex(&d.old).into());
+                        patch.insert("new".to_string(), hex(&d.new).into());
+                        Value::Object(patch)
+                    })
+                    .collect();
+                self.json.insert("diff".to_string(), patches.into());
+            }
+        }
+    }
+
+    /// The planned patches as a json array, also in human mode
+    pub fn plan(&mut self, plan: &[PlannedPatch]) {
+        let plan = serde_json::to_value(plan).expect("A patch plan is always valid json");
+        match self.format {
+            OutputFormat::Human => println!("{}", plan),
+            OutputFormat::Json => {
+                self.json.insert("plan".to_string(), plan);
+            }
+        }
+    }
+
+    pub fn osabi(&mut self, osabi: u8) {
+        match self.format {
+            OutputFormat::Human => {
+                println!("{} ({})", elf::to_str::e_osabi_to_string(osabi), osabi)
+            }
+            OutputFormat::Json => {
+                self.json.insert("osabi".to_string(), osabi.into());
+            }
+        }
+    }
+
+    /// DT_FLAGS and DT_FLAGS_1, with their bits by name
+    pub fn flags(&mut self, flags: &DynamicFlags) {
+        let entries = [
+            ("DT_FLAGS", flags.flags, sparse_elf::FLAGS_NAMES),
+            ("DT_FLAGS_1", flags.flags_1, sparse_elf::FLAGS_1_NAMES),
+        ];
+
+        match self.format {
+            OutputFormat::Human => {
+                for (d_tag, bits, names) in entries {
+                    match bits {
+                        Some(bits) => {
+                            println!("{}: {}", d_tag, flag_names(bits, names).join(" "))
+                        }
+                        None => eprintln!("{}", format!("No {} set", d_tag).yellow()),
+                    }
+                }
+            }
+            OutputFormat::Json => {
+                let mut entry = Map::new();
+                for (d_tag, bits, names) in entries {
+                    entry.insert(
+                        d_tag.to_string(),
+                        bits.map(|bits| flag_names(bits, names)).into(),
+                    );
+                }
+                self.json.insert("flags".to_string(), Value::Object(entry));
+            }
+        }
+    }
+
+    pub fn pie(&mut self, pie: bool) {
+        match self.format {
+            OutputFormat::Human => println!("{}", pie),
+            OutputFormat::Json => {
+                self.json.insert("pie".to_string(), pie.into());
+            }
+        }
+    }
+
+    pub fn dynamic_tags(&mut self, dynamic_tags: &[&str]) {
+        match self.format {
+            OutputFormat::Human => {
+                if dynamic_tags.is_empty() {
+                    eprintln!("{}", "No notable dynamic tags".yellow());
+                }
+                for d_tag in dynamic_tags {
+                    println!("{}", d_tag);
+                }
+            }
+            OutputFormat::Json => {
+                self.json
+                    .insert("dynamic_tags".to_string(), dynamic_tags.into());
+            }
+        }
+    }
+
+    /// Class, byte order, type, machine and osabi, on one line in human mode
+    pub fn summary(&mut self, summary: &ElfSummary) {
+        let class = match summary.class {
+            elf::file::Class::ELF32 => "ELF32",
+            elf::file::Class::ELF64 => "ELF64",
+        };
+        let endianness = match summary.endianness {
+            elf::endian::AnyEndian::Little => "little-endian",
+            elf::endian::AnyEndian::Big => "big-endian",
+        };
+        let e_type = elf::to_str::e_type_to_string(summary.e_type);
+        let e_machine = elf::to_str::e_machine_to_string(summary.e_machine);
+        let osabi = elf::to_str::e_osabi_to_string(summary.osabi);
+
+        match self.format {
+            OutputFormat::Human => {
+                println!(
+                    "{} {} {} {} {}",
+                    class, endianness, e_type, e_machine, osabi
+                )
+            }
+            OutputFormat::Json => {
+                let mut entry = Map::new();
+                entry.insert("class".to_string(), class.into());
+                entry.insert("endianness".to_string(), endianness.into());
+                entry.insert("type".to_string(), e_type.into());
+                entry.insert("machine".to_string(), e_machine.into());
+                entry.insert("osabi".to_string(), osabi.into());
+                self.json
+                    .insert("summary".to_string(), Value::Object(entry));
+            }
+        }
+    }
+
+    pub fn dynamic_stats(&mut self, stats: &DynamicStats) {
+        match self.format {
+            OutputFormat::Human => {
+                println!("entries: {}", stats.entries);
+                println!("DT_NULL entries: {}", stats.null_entries);
+                println!("free slots: {}", stats.free_slots());
+            }
+            OutputFormat::Json => {
+                let mut entry = Map::new();
+                entry.insert("entries".to_string(), stats.entries.into());
+                entry.insert("null_entries".to_string(), stats.null_entries.into());
+                entry.insert("free_slots".to_string(), stats.free_slots().into());
+                entry.insert("has_free_slot".to_string(), stats.has_free_slot().into());
+                self.json
+                    .insert("dynamic_stats".to_string(), Value::Object(entry));
+            }
+        }
+    }
+
+    pub fn candidates(&mut self, candidates: &[DynstrCandidate]) {
+        match self.format {
+            OutputFormat::Human => {
+                if candidates.is_empty() {
+                    eprintln!("{}", "No dynstr candidates".yellow());
+                }
+                for candidate in candidates {
+                    println!(
+                        "{} at .dynstr offset {:#x}, {} bytes",
+                        candidate.symbol, candidate.dynstr_offset, candidate.len
+                    );
+                }
+            }
+            OutputFormat::Json => {
+                let candidates: Vec<Value> = candidates
+                    .iter()
+                    .map(|candidate| {
+                        let mut entry = Map::new();
+                        entry.insert("symbol".to_string(), candidate.symbol.clone().into());
+                        entry.insert("dynstr_offset".to_string(), candidate.dynstr_offset.into());
+                        entry.insert("len".to_string(), candidate.len.into());
+                        Value::Object(entry)
+                    })
+                    .collect();
+                self.json
+                    .insert("candidates".to_string(), candidates.into());
+            }
+        }
+    }
+
+    /// Prints the collected json object, if any
+    pub fn finish(self) {
+        if self.format == OutputFormat::Json && !self.json.is_empty() {
+            println!("{}", Value::Object(self.json));
+        }
+    }
+
+    fn optional_string(&mut self, key: &str, value: Option<&str>, missing_message: &str) {
+        match self.format {
+            OutputFormat::Human => match value {
+                Some(value) => println!("{}", value),
+                None => eprintln!("{}", missing_message.yellow()),
+            },
+            OutputFormat::Json => {
+                self.json.insert(key.to_string(), value.into());
+            }
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Prints `data` in rows of 16 bytes, each prefixed with `marker` and its file offset
+fn print_hexdump(offset: usize, data: &[u8], color: impl Fn(&str) -> ColoredString, marker: char) {
+    for (row, chunk) in data.chunks(16).enumerate() {
+        let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        let line = format!(
+            "{} {:#010x}  {:<47}  |{}|",
+            marker,
+            offset + row * 16,
+            bytes.join(" "),
+            ascii
+        );
+        println!("{}", color(&line));
+    }
+}
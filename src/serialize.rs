@@ -113,6 +113,57 @@ impl ArchSerializer {
             },
         }
     }
+
+    /// Size of a class sized field like Elf32_Off or Elf64_Off
+    pub fn word_size(&self) -> usize {
+        match self.class {
+            elf::file::Class::ELF32 => 4,
+            elf::file::Class::ELF64 => 8,
+        }
+    }
+
+    /// Bytes of consecutive class sized fields
+    pub fn bytes_from_unsigned_longs(&self, vals: &[u64]) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(vals.len() * self.word_size());
+        for val in vals {
+            bytes.extend_from_slice(self.bytes_from_unsigned_long(*val)?.bytes());
+        }
+
+        Ok(bytes)
+    }
+
+    /// Bytes of a 32 bit field like p_flags, which has the same size in both classes
+    pub fn bytes_from_word32(&self, val: u32) -> [u8; 4] {
+        match self.endianness {
+            elf::endian::AnyEndian::Little => val.to_le_bytes(),
+            elf::endian::AnyEndian::Big => val.to_be_bytes(),
+        }
+    }
+
+    /// Size of an Elf32_Dyn or Elf64_Dyn entry
+    pub fn dyn_entry_size(&self) -> usize {
+        match self.class {
+            elf::file::Class::ELF32 => std::mem::size_of::<elf::dynamic::Elf32_Dyn>(),
+            elf::file::Class::ELF64 => std::mem::size_of::<elf::dynamic::Elf64_Dyn>(),
+        }
+    }
+
+    /// Bytes of a .dynamic entry, `d_tag` followed by `d_val`
+    pub fn bytes_from_dyn_entry(&self, d_tag: i64, d_val: u64) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.dyn_entry_size());
+        bytes.extend_from_slice(self.bytes_from_signed_long(d_tag)?.bytes());
+        bytes.extend_from_slice(self.bytes_from_unsigned_long(d_val)?.bytes());
+
+        Ok(bytes)
+    }
+
+    /// Offset of `d_val` within a .dynamic entry and its bytes, to change the value of an entry
+    /// but keep its tag
+    pub fn bytes_from_dyn_value(&self, d_val: u64) -> Result<(usize, Vec<u8>)> {
+        let bytes = self.bytes_from_unsigned_long(d_val)?.bytes().to_vec();
+
+        Ok((self.dyn_entry_size() / 2, bytes))
+    }
 }
 
 #[test]
@@ -149,6 +200,43 @@ fn test_be32() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_be32_limits() -> Result<()> {
+    let serializer = ArchSerializer::new(elf::file::Class::ELF32, elf::endian::AnyEndian::Big);
+
+    assert_eq!(
+        serializer
+            .bytes_from_unsigned_long(u32::MAX as u64 - 1)?
+            .bytes(),
+        [255, 255, 255, 254]
+    );
+    assert!(matches!(
+        serializer.bytes_from_unsigned_long(u32::MAX as u64 + 1),
+        Err(Error::IntConversion { .. })
+    ));
+
+    assert_eq!(
+        serializer.bytes_from_signed_long(i32::MIN as i64)?.bytes(),
+        [128, 0, 0, 0]
+    );
+    assert!(matches!(
+        serializer.bytes_from_signed_long(i32::MIN as i64 - 1),
+        Err(Error::IntConversion { .. })
+    ));
+
+    // The OS specific tags are still positive in an Elf32_Sword
+    assert_eq!(
+        serializer.bytes_from_dyn_entry(elf::abi::DT_FLAGS_1, u32::MAX as u64)?,
+        [0x6f, 0xff, 0xff, 0xfb, 255, 255, 255, 255]
+    );
+    assert!(matches!(
+        serializer.bytes_from_dyn_entry(elf::abi::DT_FLAGS_1, u32::MAX as u64 + 1),
+        Err(Error::IntConversion { .. })
+    ));
+
+    Ok(())
+}
+
 #[test]
 fn test_le64() -> Result<()> {
     let serializer = ArchSerializer::new(elf::file::Class::ELF64, elf::endian::AnyEndian::Little);
@@ -182,3 +270,42 @@ fn test_be64() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_dyn_entry() -> Result<()> {
+    let serializer = ArchSerializer::new(elf::file::Class::ELF32, elf::endian::AnyEndian::Big);
+
+    assert_eq!(
+        serializer.bytes_from_dyn_entry(elf::abi::DT_RUNPATH, 0x10)?,
+        [0, 0, 0, 29, 0, 0, 0, 16]
+    );
+
+    let serializer = ArchSerializer::new(elf::file::Class::ELF64, elf::endian::AnyEndian::Little);
+
+    assert_eq!(
+        serializer.bytes_from_dyn_entry(elf::abi::DT_RUNPATH, 0x10)?,
+        [29, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(serializer.dyn_entry_size(), 16);
+
+    Ok(())
+}
+
+#[test]
+fn test_dyn_value() -> Result<()> {
+    let serializer = ArchSerializer::new(elf::file::Class::ELF32, elf::endian::AnyEndian::Little);
+
+    assert_eq!(
+        serializer.bytes_from_dyn_value(0x10)?,
+        (4, vec![16, 0, 0, 0])
+    );
+
+    let serializer = ArchSerializer::new(elf::file::Class::ELF64, elf::endian::AnyEndian::Big);
+
+    assert_eq!(
+        serializer.bytes_from_dyn_value(0x10)?,
+        (8, vec![0, 0, 0, 0, 0, 0, 0, 16])
+    );
+
+    Ok(())
+}
@@ -1,10 +1,15 @@
 use elf::dynamic::DynamicTable;
 use elf::endian::AnyEndian;
-use elf::file::Class;
+use elf::file::{Class, FileHeader};
+use elf::relocation::{RelIterator, RelaIterator};
 use elf::section::SectionHeader;
+use elf::segment::ProgramHeader;
 use elf::string_table::StringTable;
+use elf::symbol::SymbolTable;
 use elf::{ElfStream, ParseError};
+use std::collections::{BTreeSet, HashMap};
 use std::fs::OpenOptions;
+use std::os::unix::fs::FileExt;
 use std::path::PathBuf;
 
 use snafu::prelude::*;
@@ -17,76 +22,625 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("Failed to duplicate the file handle: {}", source))]
+    DuplicateHandle { source: std::io::Error },
+
     #[snafu(display("Failed to parse elf: {}", source))]
     ParseElf { source: ParseError },
 
+    #[snafu(display("Failed to cast integer: {}", source))]
+    IntConversion { source: std::num::TryFromIntError },
+
     #[snafu(display("Elf is missing a .dynamic section"))]
     NoDynamicSection,
 
     #[snafu(display("Elf is missing .dynstr section"))]
     NoDynstrSection,
 
+    #[snafu(display("Malformed .dynstr, the entry at offset {} does not end", offset))]
+    MalformedDynstr { offset: usize },
+
     #[snafu(display("Elf is missing .interp section"))]
     NoInterpSection,
+
+    #[snafu(display(
+        "Elf is statically linked, it has no interpreter or dynamic section to patch"
+    ))]
+    StaticallyLinked,
+
+    #[snafu(display(
+        "Refusing to patch a {} ({}), only executables and shared objects are supported",
+        elf::to_str::e_type_to_string(*e_type),
+        elf::to_str::e_type_to_human_str(*e_type).unwrap_or("unknown type")
+    ))]
+    UnsupportedElfType { e_type: u16 },
+
+    #[snafu(display("Failed to get the size of the elf: {}", source))]
+    StatElf { source: std::io::Error },
+
+    #[snafu(display(
+        "{} at {:#x} with {} bytes reaches past the end of the elf ({} bytes)",
+        name,
+        offset,
+        size,
+        file_size
+    ))]
+    SectionOutOfBounds {
+        name: String,
+        offset: u64,
+        size: u64,
+        file_size: u64,
+    },
+
+    #[snafu(display(
+        "Can't read {} bytes at offset {:#x}, the elf ends at {} bytes",
+        len,
+        offset,
+        file_size
+    ))]
+    ReadPastEnd {
+        offset: u64,
+        len: usize,
+        file_size: u64,
+    },
+
+    #[snafu(display("Failed to read {} bytes at offset {:#x}: {}", len, offset, source))]
+    ReadBytes {
+        offset: u64,
+        len: usize,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "{} points to {:#x}, which no PT_LOAD segment maps",
+        elf::to_str::d_tag_to_str(*d_tag).unwrap_or("unknown"),
+        addr
+    ))]
+    UnmappedAddress { d_tag: i64, addr: u64 },
+
+    #[snafu(display("Interpreter path is not valid UTF-8: {}", source))]
+    InterpreterNotUtf8 { source: std::str::Utf8Error },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Looks up a section header by name, without any fallback
+fn find_section(
+    elf_stream: &mut ElfStream<AnyEndian, std::fs::File>,
+    name: &str,
+) -> Result<Option<SectionHeader>> {
+    Ok(elf_stream
+        .section_header_by_name(name)
+        .context(ParseElfSnafu)?
+        .copied())
+}
+
+/// Describes the file range of the first segment of type `p_type` as a section header, so that it
+/// can stand in for a section that was stripped from the section header table.
+fn segment_as_section(
+    elf_stream: &ElfStream<AnyEndian, std::fs::File>,
+    p_type: u32,
+    sh_type: u32,
+) -> Option<SectionHeader> {
+    elf_stream
+        .segments()
+        .iter()
+        .find(|phdr| phdr.p_type == p_type)
+        .map(|phdr| SectionHeader {
+            sh_name: 0,
+            sh_type,
+            sh_flags: 0,
+            sh_addr: phdr.p_vaddr,
+            sh_offset: phdr.p_offset,
+            sh_size: phdr.p_filesz,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: phdr.p_align,
+            sh_entsize: 0,
+        })
+}
+
+/// Translates the virtual address `vaddr` to a file offset through the PT_LOAD segment that maps
+/// it
+fn file_offset_of(segments: &[ProgramHeader], vaddr: u64) -> Option<u64> {
+    segments
+        .iter()
+        .find(|phdr| {
+            phdr.p_type == elf::abi::PT_LOAD
+                && phdr.p_vaddr <= vaddr
+                && vaddr - phdr.p_vaddr < phdr.p_filesz
+        })
+        .map(|phdr| vaddr - phdr.p_vaddr + phdr.p_offset)
+}
+
+/// Locates the dynamic string table through the DT_STRTAB and DT_STRSZ entries of .dynamic.
+fn dynstr_from_dynamic(
+    elf_stream: &mut ElfStream<AnyEndian, std::fs::File>,
+    shdr_dynamic: &SectionHeader,
+) -> Result<SectionHeader> {
+    let endianness = elf_stream.ehdr.endianness;
+    let class = elf_stream.ehdr.class;
+
+    let (dynamic_data, _) = elf_stream
+        .section_data(shdr_dynamic)
+        .context(ParseElfSnafu)?;
+
+    let mut dynstr_addr: Option<u64> = None;
+    let mut dynstr_size: Option<u64> = None;
+    for dyn_entry in DynamicTable::new(endianness, class, dynamic_data).iter() {
+        match dyn_entry.d_tag {
+            elf::abi::DT_STRTAB => dynstr_addr = Some(dyn_entry.d_ptr()),
+            elf::abi::DT_STRSZ => dynstr_size = Some(dyn_entry.d_val()),
+            _ => {}
+        }
+    }
+
+    let (dynstr_addr, dynstr_size) = match (dynstr_addr, dynstr_size) {
+        (Some(addr), Some(size)) => (addr, size),
+        _ => return Err(Error::NoDynstrSection),
+    };
+
+    // DT_STRTAB is a virtual address, translate it through the PT_LOAD segment that maps it
+    let dynstr_offset =
+        file_offset_of(elf_stream.segments(), dynstr_addr).ok_or(Error::NoDynstrSection)?;
+
+    Ok(SectionHeader {
+        sh_name: 0,
+        sh_type: elf::abi::SHT_STRTAB,
+        sh_flags: 0,
+        sh_addr: dynstr_addr,
+        sh_offset: dynstr_offset,
+        sh_size: dynstr_size,
+        sh_link: 0,
+        sh_info: 0,
+        sh_addralign: 1,
+        sh_entsize: 0,
+    })
+}
+
+/// Entry counts of .dynamic, to tell whether a new entry can be added without repointing one
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DynamicStats {
+    pub entries: usize,
+    /// DT_NULL entries from the first one to the end of .dynamic
+    pub null_entries: usize,
+}
+
+impl DynamicStats {
+    /// DT_NULL entries that can be used for new entries, one has to stay as the terminator
+    pub fn free_slots(&self) -> usize {
+        self.null_entries.saturating_sub(1)
+    }
+
+    pub fn has_free_slot(&self) -> bool {
+        self.free_slots() > 0
+    }
+}
+
+/// The d_val of DT_FLAGS and DT_FLAGS_1, None if the elf has no such entry
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DynamicFlags {
+    pub flags: Option<u64>,
+    pub flags_1: Option<u64>,
+}
+
+/// Names of the DT_FLAGS bits
+pub const FLAGS_NAMES: &[(&str, i64)] = &[
+    ("DF_ORIGIN", elf::abi::DF_ORIGIN),
+    ("DF_SYMBOLIC", elf::abi::DF_SYMBOLIC),
+    ("DF_TEXTREL", elf::abi::DF_TEXTREL),
+    ("DF_BIND_NOW", elf::abi::DF_BIND_NOW),
+    ("DF_STATIC_TLS", elf::abi::DF_STATIC_TLS),
+];
+
+/// Names of the DT_FLAGS_1 bits
+pub const FLAGS_1_NAMES: &[(&str, i64)] = &[
+    ("DF_1_NOW", elf::abi::DF_1_NOW),
+    ("DF_1_GLOBAL", elf::abi::DF_1_GLOBAL),
+    ("DF_1_GROUP", elf::abi::DF_1_GROUP),
+    ("DF_1_NODELETE", elf::abi::DF_1_NODELETE),
+    ("DF_1_LOADFLTR", elf::abi::DF_1_LOADFLTR),
+    ("DF_1_INITFIRST", elf::abi::DF_1_INITFIRST),
+    ("DF_1_NOOPEN", elf::abi::DF_1_NOOPEN),
+    ("DF_1_ORIGIN", elf::abi::DF_1_ORIGIN),
+    ("DF_1_DIRECT", elf::abi::DF_1_DIRECT),
+    ("DF_1_TRANS", elf::abi::DF_1_TRANS),
+    ("DF_1_INTERPOSE", elf::abi::DF_1_INTERPOSE),
+    ("DF_1_NODEFLIB", elf::abi::DF_1_NODEFLIB),
+    ("DF_1_NODUMP", elf::abi::DF_1_NODUMP),
+    ("DF_1_CONFALT", elf::abi::DF_1_CONFALT),
+    ("DF_1_ENDFILTEE", elf::abi::DF_1_ENDFILTEE),
+    ("DF_1_DISPRELDNE", elf::abi::DF_1_DISPRELDNE),
+    ("DF_1_DISPRELPND", elf::abi::DF_1_DISPRELPND),
+    ("DF_1_NODIRECT", elf::abi::DF_1_NODIRECT),
+    ("DF_1_IGNMULDEF", elf::abi::DF_1_IGNMULDEF),
+    ("DF_1_NOKSYMS", elf::abi::DF_1_NOKSYMS),
+    ("DF_1_NOHDR", elf::abi::DF_1_NOHDR),
+    ("DF_1_EDITED", elf::abi::DF_1_EDITED),
+    ("DF_1_NORELOC", elf::abi::DF_1_NORELOC),
+    ("DF_1_SYMINTPOSE", elf::abi::DF_1_SYMINTPOSE),
+    ("DF_1_GLOBAUDIT", elf::abi::DF_1_GLOBAUDIT),
+    ("DF_1_SINGLETON", elf::abi::DF_1_SINGLETON),
+    ("DF_1_STUB", elf::abi::DF_1_STUB),
+    ("DF_1_PIE", elf::abi::DF_1_PIE),
+    ("DF_1_KMOD", elf::abi::DF_1_KMOD),
+    ("DF_1_WEAKFILTER", elf::abi::DF_1_WEAKFILTER),
+    ("DF_1_NOCOMMON", elf::abi::DF_1_NOCOMMON),
+];
+
+/// .dynamic tags that tell something about how the loader treats the binary
+pub const NOTABLE_DYNAMIC_TAGS: &[(&str, i64)] = &[
+    ("DT_DEBUG", elf::abi::DT_DEBUG),
+    ("DT_HASH", elf::abi::DT_HASH),
+    ("DT_GNU_HASH", elf::abi::DT_GNU_HASH),
+    ("DT_BIND_NOW", elf::abi::DT_BIND_NOW),
+    ("DT_TEXTREL", elf::abi::DT_TEXTREL),
+];
+
+/// Names of the bits set in `bits`, looked up in a table like [`FLAGS_NAMES`]. Bits without a
+/// name are given as hex.
+pub fn flag_names(bits: u64, names: &[(&str, i64)]) -> Vec<String> {
+    (0..u64::BITS)
+        .map(|shift| 1u64 << shift)
+        .filter(|bit| bits & bit != 0)
+        .map(|bit| {
+            names
+                .iter()
+                .find(|(_, known)| *known as u64 == bit)
+                .map_or(format!("{:#x}", bit), |(name, _)| name.to_string())
+        })
+        .collect()
+}
+
+/// The elf header fields that tell what kind of binary this is
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ElfSummary {
+    pub class: Class,
+    pub endianness: AnyEndian,
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub osabi: u8,
+}
+
 pub struct SparseElf {
     elf_stream: ElfStream<AnyEndian, std::fs::File>,
+    /// Shares the open file description with `elf_stream`
+    file: std::fs::File,
 
     pub shdr_dynamic: SectionHeader,
     pub shdr_dynstr: SectionHeader,
-    pub shdr_interp: SectionHeader,
+    /// None for elfs without an interpreter, like most shared libraries
+    pub shdr_interp: Option<SectionHeader>,
+
+    /// Section headers looked up by name, including the ones that do not exist
+    sections: HashMap<String, Option<SectionHeader>>,
+
+    /// Class and byte order that .dynamic is read with instead of the ones in the elf header
+    forced_class: Option<Class>,
+    forced_endianness: Option<AnyEndian>,
 }
 
 impl SparseElf {
+    /// Opens the elf read-only
     pub fn new(file_path: &PathBuf) -> Result<Self> {
+        Self::open(file_path, false)
+    }
+
+    /// Opens the elf, for writing as well if `writable` is set.
+    ///
+    /// Parsing and writing share the same handle, see [`SparseElf::file`]. Only executables and
+    /// shared objects are accepted.
+    pub fn open(file_path: &PathBuf, writable: bool) -> Result<Self> {
+        Self::open_elf(file_path, writable, true)
+    }
+
+    /// Like [`SparseElf::open`], but accepts any elf type, e.g. relocatable objects
+    pub fn open_any_type(file_path: &PathBuf, writable: bool) -> Result<Self> {
+        Self::open_elf(file_path, writable, false)
+    }
+
+    fn open_elf(file_path: &PathBuf, writable: bool, check_type: bool) -> Result<Self> {
         let file = OpenOptions::new()
             .read(true)
+            .write(writable)
             .open(file_path)
             .context(OpenElfSnafu {
                 file_path: file_path.to_string_lossy(),
             })?;
 
-        let mut elf_stream = ElfStream::open_stream(file).context(ParseElfSnafu)?;
+        Self::from_file(file, !check_type)
+    }
 
-        let shdr_dynamic = *elf_stream
-            .section_header_by_name(".dynamic")
-            .context(ParseElfSnafu)?
-            .ok_or(Error::NoDynamicSection)?;
+    /// Parses an already opened elf. The elf is only writable if `file` is.
+    ///
+    /// The caller keeps control over how the file was opened, e.g. its flags or locks. Accepts
+    /// any elf type if `allow_any_type` is set, like [`SparseElf::open_any_type`].
+    pub fn from_file(file: std::fs::File, allow_any_type: bool) -> Result<Self> {
+        Self::parse(file, !allow_any_type)
+    }
 
-        let shdr_dynstr = *elf_stream
-            .section_header_by_name(".dynstr")
-            .context(ParseElfSnafu)?
-            .ok_or(Error::NoDynstrSection)?;
+    /// Reads .dynamic with the field width of `class`, whatever EI_CLASS says. Everything else
+    /// is still parsed as the elf header describes it.
+    pub fn set_force_class(&mut self, class: Class) {
+        self.forced_class = Some(class);
+    }
 
-        let shdr_interp = *elf_stream
-            .section_header_by_name(".interp")
-            .context(ParseElfSnafu)?
-            .ok_or(Error::NoInterpSection)?;
+    /// Reads .dynamic in the byte order `endianness`, whatever EI_DATA says. Everything else is
+    /// still parsed as the elf header describes it.
+    pub fn set_force_endianness(&mut self, endianness: AnyEndian) {
+        self.forced_endianness = Some(endianness);
+    }
+
+    /// The class .dynamic is read with, see [`SparseElf::set_force_class`]
+    pub fn dynamic_class(&self) -> Class {
+        self.forced_class.unwrap_or(self.class())
+    }
+
+    /// The byte order .dynamic is read in, see [`SparseElf::set_force_endianness`]
+    pub fn dynamic_endianness(&self) -> AnyEndian {
+        self.forced_endianness.unwrap_or(self.endianess())
+    }
+
+    fn parse(file: std::fs::File, check_type: bool) -> Result<Self> {
+        let stream_file = file.try_clone().context(DuplicateHandleSnafu)?;
+
+        let mut elf_stream = ElfStream::open_stream(stream_file).context(ParseElfSnafu)?;
+
+        let e_type = elf_stream.ehdr.e_type;
+        if check_type && e_type != elf::abi::ET_EXEC && e_type != elf::abi::ET_DYN {
+            return Err(Error::UnsupportedElfType { e_type });
+        }
+
+        // Report a static executable as such, instead of the first section that is missing
+        let is_linked_program = e_type == elf::abi::ET_EXEC || e_type == elf::abi::ET_DYN;
+        let is_dynamically_linked = elf_stream
+            .segments()
+            .iter()
+            .any(|phdr| phdr.p_type == elf::abi::PT_DYNAMIC || phdr.p_type == elf::abi::PT_INTERP);
+        if is_linked_program && !is_dynamically_linked {
+            return Err(Error::StaticallyLinked);
+        }
+
+        if elf_stream.section_headers().is_empty() {
+            return Self::from_program_headers(elf_stream, file);
+        }
+
+        let shdr_dynamic = match find_section(&mut elf_stream, ".dynamic")? {
+            Some(shdr) => shdr,
+            None => segment_as_section(&elf_stream, elf::abi::PT_DYNAMIC, elf::abi::SHT_DYNAMIC)
+                .ok_or(Error::NoDynamicSection)?,
+        };
+
+        let shdr_dynstr = match find_section(&mut elf_stream, ".dynstr")? {
+            Some(shdr) => shdr,
+            None => dynstr_from_dynamic(&mut elf_stream, &shdr_dynamic)?,
+        };
+
+        let shdr_interp = match find_section(&mut elf_stream, ".interp")? {
+            Some(shdr) => Some(shdr),
+            None => segment_as_section(&elf_stream, elf::abi::PT_INTERP, elf::abi::SHT_PROGBITS),
+        };
+
+        Self::with_sections(elf_stream, file, shdr_dynamic, shdr_dynstr, shdr_interp)
+    }
+
+    /// The three sections the patcher needs are cached under their names, so
+    /// [`SparseElf::section`] returns them even if they were located through the program headers.
+    ///
+    /// They have to lie within the file, as their sizes decide how much is read and written.
+    fn with_sections(
+        elf_stream: ElfStream<AnyEndian, std::fs::File>,
+        file: std::fs::File,
+        shdr_dynamic: SectionHeader,
+        shdr_dynstr: SectionHeader,
+        shdr_interp: Option<SectionHeader>,
+    ) -> Result<Self> {
+        let file_size = file.metadata().context(StatElfSnafu)?.len();
+
+        let named_shdrs = [
+            (".dynamic", Some(shdr_dynamic)),
+            (".dynstr", Some(shdr_dynstr)),
+            (".interp", shdr_interp),
+        ];
+
+        for (name, shdr) in named_shdrs
+            .iter()
+            .filter_map(|(name, shdr)| shdr.map(|shdr| (name, shdr)))
+        {
+            let in_bounds = shdr
+                .sh_offset
+                .checked_add(shdr.sh_size)
+                .is_some_and(|end| end <= file_size);
+
+            if !in_bounds {
+                return Err(Error::SectionOutOfBounds {
+                    name: name.to_string(),
+                    offset: shdr.sh_offset,
+                    size: shdr.sh_size,
+                    file_size,
+                });
+            }
+        }
+
+        let sections = named_shdrs
+            .into_iter()
+            .map(|(name, shdr)| (name.to_string(), shdr))
+            .collect();
 
         Ok(Self {
             elf_stream,
+            file,
             shdr_dynamic,
             shdr_dynstr,
             shdr_interp,
+            sections,
+            forced_class: None,
+            forced_endianness: None,
         })
     }
 
-    pub fn dynamic(&mut self) -> Result<DynamicTable<AnyEndian>> {
+    /// Locates everything through the program headers, for elfs without section headers.
+    fn from_program_headers(
+        mut elf_stream: ElfStream<AnyEndian, std::fs::File>,
+        file: std::fs::File,
+    ) -> Result<Self> {
+        let shdr_dynamic =
+            segment_as_section(&elf_stream, elf::abi::PT_DYNAMIC, elf::abi::SHT_DYNAMIC)
+                .ok_or(Error::NoDynamicSection)?;
+
+        let shdr_dynstr = dynstr_from_dynamic(&mut elf_stream, &shdr_dynamic)?;
+
+        let shdr_interp =
+            segment_as_section(&elf_stream, elf::abi::PT_INTERP, elf::abi::SHT_PROGBITS);
+
+        Self::with_sections(elf_stream, file, shdr_dynamic, shdr_dynstr, shdr_interp)
+    }
+
+    /// The underlying file handle. It is only writable if the elf was opened as such.
+    pub fn file(&self) -> &std::fs::File {
+        &self.file
+    }
+
+    /// Reads `len` bytes at the file `offset`, without going through the parsed views.
+    ///
+    /// The read is positional, so it doesn't move the file cursor the parser relies on.
+    pub fn raw_bytes_at(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let file_size = self.file.metadata().context(StatElfSnafu)?.len();
+        if offset
+            .checked_add(len as u64)
+            .is_none_or(|end| end > file_size)
+        {
+            return Err(Error::ReadPastEnd {
+                offset,
+                len,
+                file_size,
+            });
+        }
+
+        let mut data = vec![0; len];
+        self.file
+            .read_exact_at(&mut data, offset)
+            .context(ReadBytesSnafu { offset, len })?;
+
+        Ok(data)
+    }
+
+    /// Returns the header of the section called `name`, or None if the elf has no such section
+    pub fn section(&mut self, name: &str) -> Result<Option<SectionHeader>> {
+        if let Some(shdr) = self.sections.get(name) {
+            return Ok(*shdr);
+        }
+
+        let shdr = find_section(&mut self.elf_stream, name)?;
+        self.sections.insert(name.to_string(), shdr);
+
+        Ok(shdr)
+    }
+
+    pub fn file_header(&self) -> FileHeader<AnyEndian> {
+        self.elf_stream.ehdr
+    }
+
+    /// All program headers, in the order of the program header table
+    pub fn segments(&self) -> Vec<ProgramHeader> {
+        self.elf_stream.segments().clone()
+    }
+
+    /// The first program header of type `p_type`
+    pub fn segment(&self, p_type: u32) -> Option<ProgramHeader> {
         self.elf_stream
-            .dynamic()
-            .context(ParseElfSnafu)?
-            .ok_or(Error::NoDynamicSection)
+            .segments()
+            .iter()
+            .find(|phdr| phdr.p_type == p_type)
+            .copied()
     }
 
-    pub fn dynstr(&mut self) -> Result<StringTable> {
+    /// All section headers with their names, empty if the elf has none
+    pub fn section_headers(&mut self) -> Result<Vec<(String, SectionHeader)>> {
+        let (shdrs, strtab) = self
+            .elf_stream
+            .section_headers_with_strtab()
+            .context(ParseElfSnafu)?;
+
+        Ok(shdrs
+            .iter()
+            .map(|shdr| {
+                let name = strtab
+                    .as_ref()
+                    .and_then(|strtab| strtab.get(shdr.sh_name as usize).ok())
+                    .unwrap_or_default();
+                (name.to_string(), *shdr)
+            })
+            .collect())
+    }
+
+    /// File offset of the section called `name`
+    pub fn section_offset(&mut self, name: &str) -> Result<Option<u64>> {
+        Ok(self.section(name)?.map(|shdr| shdr.sh_offset))
+    }
+
+    pub fn dynamic(&mut self) -> Result<DynamicTable<'_, AnyEndian>> {
+        let endianness = self.dynamic_endianness();
+        let class = self.dynamic_class();
+        let shdr_dynamic = self.shdr_dynamic;
+
+        let (dynamic_data, _) = self
+            .elf_stream
+            .section_data(&shdr_dynamic)
+            .context(ParseElfSnafu)?;
+
+        Ok(DynamicTable::new(endianness, class, dynamic_data))
+    }
+
+    pub fn dynstr(&mut self) -> Result<StringTable<'_>> {
         self.elf_stream
             .section_data_as_strtab(&self.shdr_dynstr)
             .context(ParseElfSnafu)
     }
 
+    /// Returns the .dynstr string starting at `offset`, e.g. the one a .dynamic d_val points to
+    pub fn dynstr_at(&mut self, offset: usize) -> Result<String> {
+        Ok(self
+            .dynstr()?
+            .get(offset)
+            .context(ParseElfSnafu)?
+            .to_string())
+    }
+
+    /// Returns the names of all DT_NEEDED libraries, in the order they appear in .dynamic
+    pub fn needed(&mut self) -> Result<Vec<String>> {
+        let needed_offsets = self
+            .dynamic()?
+            .iter()
+            .filter(|d| d.d_tag == elf::abi::DT_NEEDED)
+            .map(|d| usize::try_from(d.d_val()).context(IntConversionSnafu))
+            .collect::<Result<Vec<usize>>>()?;
+
+        needed_offsets
+            .into_iter()
+            .map(|offset| self.dynstr_at(offset))
+            .collect()
+    }
+
+    /// Returns the interpreter path, without the terminating NUL
+    pub fn interpreter(&mut self) -> Result<String> {
+        let shdr_interp = self.shdr_interp.ok_or(Error::NoInterpSection)?;
+        let (interp_data, _) = self
+            .elf_stream
+            .section_data(&shdr_interp)
+            .context(ParseElfSnafu)?;
+
+        let interp_len = interp_data
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(interp_data.len());
+
+        let interpreter =
+            std::str::from_utf8(&interp_data[..interp_len]).context(InterpreterNotUtf8Snafu)?;
+
+        Ok(interpreter.to_string())
+    }
+
     pub fn class(&self) -> Class {
         self.elf_stream.ehdr.class
     }
@@ -95,29 +649,349 @@ impl SparseElf {
         self.elf_stream.ehdr.endianness
     }
 
-    pub fn dynstr_contains(&mut self, needle: &str) -> Result<bool> {
+    /// The EI_OSABI byte of the elf header
+    pub fn osabi(&self) -> u8 {
+        self.elf_stream.ehdr.osabi
+    }
+
+    pub fn e_type(&self) -> u16 {
+        self.elf_stream.ehdr.e_type
+    }
+
+    pub fn e_machine(&self) -> u16 {
+        self.elf_stream.ehdr.e_machine
+    }
+
+    pub fn summary(&self) -> ElfSummary {
+        ElfSummary {
+            class: self.class(),
+            endianness: self.endianess(),
+            e_type: self.e_type(),
+            e_machine: self.e_machine(),
+            osabi: self.osabi(),
+        }
+    }
+
+    /// Returns every string in .dynstr together with its offset, skipping the leading empty string.
+    pub fn dynstr_entries(&mut self) -> Result<Vec<(usize, &str)>> {
+        let dynstr_sh_size = self.shdr_dynstr.sh_size;
+        let dynstr_data = self.dynstr()?;
+
+        let mut entries = Vec::new();
         let mut dynstr_index = 1;
-        while (dynstr_index as u64) < self.shdr_dynstr.sh_size {
-            let entry = self.dynstr()?.get(dynstr_index).context(ParseElfSnafu)?;
+        while (dynstr_index as u64) < dynstr_sh_size {
+            let entry = dynstr_data.get(dynstr_index).context(ParseElfSnafu)?;
+            entries.push((dynstr_index, entry));
+
+            // Every entry is followed by its NUL, so the walk has to move forward
+            dynstr_index = match dynstr_index.checked_add(entry.len() + 1) {
+                Some(next_index) if next_index > dynstr_index => next_index,
+                _ => {
+                    return Err(Error::MalformedDynstr {
+                        offset: dynstr_index,
+                    })
+                }
+            };
+        }
 
-            if entry.contains(needle) {
+        Ok(entries)
+    }
+
+    pub fn dynstr_contains(&mut self, needle: &str) -> Result<bool> {
+        Ok(self
+            .dynstr_entries()?
+            .iter()
+            .any(|(_, entry)| entry.contains(needle)))
+    }
+
+    /// Whether a dynamic relocation references a symbol named by the .dynstr entry at
+    /// `dynstr_offset`. Weak symbols count too, a library may still provide them at runtime.
+    /// Without section headers, the tables are located through .dynamic.
+    pub fn is_relocation_target(&mut self, dynstr_offset: usize) -> Result<bool> {
+        let symbol_indices: Vec<u32> = match self
+            .elf_stream
+            .dynamic_symbol_table()
+            .context(ParseElfSnafu)?
+        {
+            Some((symtab, _)) => symtab
+                .iter()
+                .enumerate()
+                .filter(|(_, sym)| sym.st_name as usize == dynstr_offset)
+                .map(|(i, _)| i as u32)
+                .collect(),
+            // Without section headers, the tables have to be found through .dynamic
+            None => return self.is_relocation_target_from_dynamic(dynstr_offset),
+        };
+
+        if symbol_indices.is_empty() {
+            return Ok(false);
+        }
+
+        let section_headers = self.elf_stream.section_headers();
+        let dynsym_index = section_headers
+            .iter()
+            .position(|shdr| shdr.sh_type == elf::abi::SHT_DYNSYM);
+
+        let reloc_shdrs: Vec<SectionHeader> = section_headers
+            .iter()
+            .filter(|shdr| {
+                (shdr.sh_type == elf::abi::SHT_REL || shdr.sh_type == elf::abi::SHT_RELA)
+                    && Some(shdr.sh_link as usize) == dynsym_index
+            })
+            .copied()
+            .collect();
+
+        for shdr in reloc_shdrs {
+            let referenced = if shdr.sh_type == elf::abi::SHT_RELA {
+                self.elf_stream
+                    .section_data_as_relas(&shdr)
+                    .context(ParseElfSnafu)?
+                    .any(|rela| symbol_indices.contains(&rela.r_sym))
+            } else {
+                self.elf_stream
+                    .section_data_as_rels(&shdr)
+                    .context(ParseElfSnafu)?
+                    .any(|rel| symbol_indices.contains(&rel.r_sym))
+            };
+
+            if referenced {
                 return Ok(true);
             }
-            dynstr_index += entry.len() + 1;
         }
 
         Ok(false)
     }
 
-    pub fn dynamic_contains(&mut self, d_tag: i64) -> Result<bool> {
+    /// Like [`SparseElf::is_relocation_target`], but finds the relocation tables through DT_RELA,
+    /// DT_REL and DT_JMPREL and the symbols through DT_SYMTAB
+    fn is_relocation_target_from_dynamic(&mut self, dynstr_offset: usize) -> Result<bool> {
+        let endianness = self.endianess();
+        let class = self.class();
+
+        let mut d_vals: HashMap<i64, u64> = HashMap::new();
+        for dyn_entry in self.dynamic()?.iter() {
+            d_vals.entry(dyn_entry.d_tag).or_insert(dyn_entry.d_val());
+        }
+
+        let Some(&symtab_addr) = d_vals.get(&elf::abi::DT_SYMTAB) else {
+            return Ok(false);
+        };
+        let sym_size = match d_vals.get(&elf::abi::DT_SYMENT) {
+            Some(&size) => usize::try_from(size).context(IntConversionSnafu)?,
+            None if class == Class::ELF32 => 16,
+            None => 24,
+        };
+
+        let plt_is_rela = d_vals.get(&elf::abi::DT_PLTREL) == Some(&(elf::abi::DT_RELA as u64));
+        let tables = [
+            (elf::abi::DT_RELA, elf::abi::DT_RELASZ, true),
+            (elf::abi::DT_REL, elf::abi::DT_RELSZ, false),
+            (elf::abi::DT_JMPREL, elf::abi::DT_PLTRELSZ, plt_is_rela),
+        ];
+
+        let segments = self.segments();
+        let mut symbol_indices = BTreeSet::new();
+        for (addr_tag, size_tag, is_rela) in tables {
+            let (Some(&addr), Some(&size)) = (d_vals.get(&addr_tag), d_vals.get(&size_tag)) else {
+                continue;
+            };
+            let offset = file_offset_of(&segments, addr).ok_or(Error::UnmappedAddress {
+                d_tag: addr_tag,
+                addr,
+            })?;
+            let data =
+                self.raw_bytes_at(offset, usize::try_from(size).context(IntConversionSnafu)?)?;
+
+            if is_rela {
+                symbol_indices
+                    .extend(RelaIterator::new(endianness, class, &data).map(|rela| rela.r_sym));
+            } else {
+                symbol_indices
+                    .extend(RelIterator::new(endianness, class, &data).map(|rel| rel.r_sym));
+            }
+        }
+
+        let symtab_offset =
+            file_offset_of(&segments, symtab_addr).ok_or(Error::UnmappedAddress {
+                d_tag: elf::abi::DT_SYMTAB,
+                addr: symtab_addr,
+            })?;
+
+        // Symbol 0 is the undefined symbol, relocations without a symbol refer to it
+        for symbol_index in symbol_indices.into_iter().filter(|i| *i != 0) {
+            // A symbol past the end of the elf fails to be read
+            let symbol_offset = symtab_offset.saturating_add(symbol_index as u64 * sym_size as u64);
+            let data = self.raw_bytes_at(symbol_offset, sym_size)?;
+            let symbol = SymbolTable::new(endianness, class, &data)
+                .get(0)
+                .context(ParseElfSnafu)?;
+
+            if symbol.st_name as usize == dynstr_offset {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// .dynstr offsets referenced by dynamic symbol names and string valued .dynamic entries
+    pub fn dynstr_references(&mut self) -> Result<Vec<usize>> {
+        let mut references: Vec<usize> = match self
+            .elf_stream
+            .dynamic_symbol_table()
+            .context(ParseElfSnafu)?
+        {
+            Some((symtab, _)) => symtab.iter().map(|sym| sym.st_name as usize).collect(),
+            None => Vec::new(),
+        };
+
+        for dyn_entry in self.dynamic()?.iter() {
+            if matches!(
+                dyn_entry.d_tag,
+                elf::abi::DT_NEEDED
+                    | elf::abi::DT_SONAME
+                    | elf::abi::DT_RPATH
+                    | elf::abi::DT_RUNPATH
+            ) {
+                references.push(usize::try_from(dyn_entry.d_val()).context(IntConversionSnafu)?);
+            }
+        }
+
+        Ok(references)
+    }
+
+    /// Position of the first .dynamic entry with `d_tag`
+    pub fn find_dynamic(&mut self, d_tag: i64) -> Result<Option<usize>> {
         let section_dynamic = self.dynamic()?;
 
         for i in 0..section_dynamic.len() {
             let dyn_entry = section_dynamic.get(i).context(ParseElfSnafu)?;
             if dyn_entry.d_tag == d_tag {
-                return Ok(true);
+                return Ok(Some(i));
             }
         }
-        Ok(false)
+        Ok(None)
+    }
+
+    pub fn dynamic_contains(&mut self, d_tag: i64) -> Result<bool> {
+        Ok(self.find_dynamic(d_tag)?.is_some())
+    }
+
+    /// Names of the [`NOTABLE_DYNAMIC_TAGS`] present in .dynamic, in the order of that table
+    pub fn notable_dynamic_tags(&mut self) -> Result<Vec<&'static str>> {
+        let section_dynamic = self.dynamic()?;
+
+        let mut present = vec![false; NOTABLE_DYNAMIC_TAGS.len()];
+        for i in 0..section_dynamic.len() {
+            let dyn_entry = section_dynamic.get(i).context(ParseElfSnafu)?;
+            if let Some(index) = NOTABLE_DYNAMIC_TAGS
+                .iter()
+                .position(|(_, d_tag)| *d_tag == dyn_entry.d_tag)
+            {
+                present[index] = true;
+            }
+        }
+
+        Ok(NOTABLE_DYNAMIC_TAGS
+            .iter()
+            .zip(present)
+            .filter(|(_, present)| *present)
+            .map(|((name, _), _)| *name)
+            .collect())
+    }
+
+    pub fn dynamic_stats(&mut self) -> Result<DynamicStats> {
+        let section_dynamic = self.dynamic()?;
+
+        let entries = section_dynamic.len();
+        let null_entries = section_dynamic
+            .iter()
+            .position(|d| d.d_tag == elf::abi::DT_NULL)
+            .map_or(0, |first_null| entries - first_null);
+
+        Ok(DynamicStats {
+            entries,
+            null_entries,
+        })
+    }
+
+    /// Returns the string referenced by DT_RUNPATH, or DT_RPATH if there is no DT_RUNPATH.
+    ///
+    /// Only the first entry counts, see [`Self::runpaths`] for binaries with more than one.
+    pub fn runpath(&mut self) -> Result<Option<String>> {
+        match self.dynamic_string(elf::abi::DT_RUNPATH)? {
+            Some(runpath) => Ok(Some(runpath)),
+            None => self.dynamic_string(elf::abi::DT_RPATH),
+        }
+    }
+
+    /// Returns the strings of every DT_RUNPATH and DT_RPATH entry, in the order of .dynamic.
+    ///
+    /// Linkers write at most one, more are only found in unusual or malformed binaries.
+    pub fn runpaths(&mut self) -> Result<Vec<String>> {
+        let runpath_offsets = self
+            .dynamic()?
+            .iter()
+            .filter(|d| d.d_tag == elf::abi::DT_RUNPATH || d.d_tag == elf::abi::DT_RPATH)
+            .map(|d| usize::try_from(d.d_val()).context(IntConversionSnafu))
+            .collect::<Result<Vec<usize>>>()?;
+
+        runpath_offsets
+            .into_iter()
+            .map(|offset| self.dynstr_at(offset))
+            .collect()
+    }
+
+    /// Returns the string referenced by DT_SONAME
+    pub fn soname(&mut self) -> Result<Option<String>> {
+        self.dynamic_string(elf::abi::DT_SONAME)
+    }
+
+    /// Returns the DF_* bits of DT_FLAGS and the DF_1_* bits of DT_FLAGS_1
+    pub fn flags(&mut self) -> Result<DynamicFlags> {
+        Ok(DynamicFlags {
+            flags: self.dynamic_value(elf::abi::DT_FLAGS)?,
+            flags_1: self.flags_1()?,
+        })
+    }
+
+    /// Whether the elf is a position independent executable: an ET_DYN with an interpreter or
+    /// with DF_1_PIE set
+    pub fn is_pie(&mut self) -> Result<bool> {
+        if self.e_type() != elf::abi::ET_DYN {
+            return Ok(false);
+        }
+
+        if self.segment(elf::abi::PT_INTERP).is_some() {
+            return Ok(true);
+        }
+
+        Ok(self
+            .flags_1()?
+            .is_some_and(|flags_1| flags_1 & elf::abi::DF_1_PIE as u64 != 0))
+    }
+
+    /// Returns the DF_1_* bits of DT_FLAGS_1
+    pub fn flags_1(&mut self) -> Result<Option<u64>> {
+        self.dynamic_value(elf::abi::DT_FLAGS_1)
+    }
+
+    /// Returns `d_val` of the first .dynamic entry with `d_tag`
+    pub fn dynamic_value(&mut self, d_tag: i64) -> Result<Option<u64>> {
+        Ok(self
+            .dynamic()?
+            .iter()
+            .find(|d| d.d_tag == d_tag)
+            .map(|d| d.d_val()))
+    }
+
+    /// Returns the .dynstr string referenced by the first .dynamic entry with `d_tag`
+    pub fn dynamic_string(&mut self, d_tag: i64) -> Result<Option<String>> {
+        let dynstr_offset = match self.dynamic_value(d_tag)? {
+            Some(d_val) => usize::try_from(d_val).context(IntConversionSnafu)?,
+            None => return Ok(None),
+        };
+
+        self.dynstr_at(dynstr_offset).map(Some)
     }
 }
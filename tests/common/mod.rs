@@ -0,0 +1,303 @@
+//! Assembles minimal elf images, so the patch logic can be tested without host binaries.
+//!
+//! The images only contain what the patcher reads: .interp, .dynstr with a `__gmon_start__`
+//! candidate, .dynamic with spare DT_NULL entries and the program headers mapping them. Shared
+//! objects have a DT_SONAME instead of .interp.
+
+use std::{fs, path::Path};
+
+#[derive(Copy, Clone, Debug)]
+pub enum Class {
+    Elf32,
+    Elf64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+pub const INTERP: &str = "/lib/ld-minimal.so.1";
+pub const NEEDED: &str = "libc.so.6";
+pub const SONAME: &str = "libminimal.so.1";
+
+const INTERP_OFFSET: usize = 0x100;
+const INTERP_SIZE: usize = 0x20;
+pub const DYNSTR_OFFSET: usize = 0x120;
+pub const DYNAMIC_OFFSET: usize = 0x140;
+
+struct Writer {
+    class: Class,
+    endian: Endian,
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, offset: usize, bytes: &[u8]) -> usize {
+        if self.data.len() < offset + bytes.len() {
+            self.data.resize(offset + bytes.len(), 0);
+        }
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        offset + bytes.len()
+    }
+
+    fn u16(&mut self, offset: usize, value: u16) -> usize {
+        match self.endian {
+            Endian::Little => self.bytes(offset, &value.to_le_bytes()),
+            Endian::Big => self.bytes(offset, &value.to_be_bytes()),
+        }
+    }
+
+    fn u32(&mut self, offset: usize, value: u32) -> usize {
+        match self.endian {
+            Endian::Little => self.bytes(offset, &value.to_le_bytes()),
+            Endian::Big => self.bytes(offset, &value.to_be_bytes()),
+        }
+    }
+
+    /// A class sized word, like Elf32_Addr or Elf64_Addr
+    fn word(&mut self, offset: usize, value: u64) -> usize {
+        match (self.class, self.endian) {
+            (Class::Elf32, _) => self.u32(offset, value as u32),
+            (Class::Elf64, Endian::Little) => self.bytes(offset, &value.to_le_bytes()),
+            (Class::Elf64, Endian::Big) => self.bytes(offset, &value.to_be_bytes()),
+        }
+    }
+
+    fn word_size(&self) -> usize {
+        match self.class {
+            Class::Elf32 => 4,
+            Class::Elf64 => 8,
+        }
+    }
+
+    fn phdr(
+        &mut self,
+        offset: usize,
+        p_type: u32,
+        p_flags: u32,
+        p_offset: u64,
+        p_filesz: u64,
+        p_align: u64,
+    ) -> usize {
+        // p_flags is the second field of Elf64_Phdr, but the seventh of Elf32_Phdr
+        let mut o = self.u32(offset, p_type);
+        if let Class::Elf64 = self.class {
+            o = self.u32(o, p_flags);
+        }
+        o = self.word(o, p_offset);
+        o = self.word(o, p_offset);
+        o = self.word(o, p_offset);
+        o = self.word(o, p_filesz);
+        o = self.word(o, p_filesz);
+        if let Class::Elf32 = self.class {
+            o = self.u32(o, p_flags);
+        }
+        self.word(o, p_align)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn shdr(
+        &mut self,
+        offset: usize,
+        sh_name: u32,
+        sh_type: u32,
+        sh_flags: u64,
+        sh_offset: u64,
+        sh_size: u64,
+        sh_link: u32,
+        sh_entsize: u64,
+    ) -> usize {
+        let sh_addr = if sh_flags & 0x2 != 0 { sh_offset } else { 0 };
+        let mut o = self.u32(offset, sh_name);
+        o = self.u32(o, sh_type);
+        o = self.word(o, sh_flags);
+        o = self.word(o, sh_addr);
+        o = self.word(o, sh_offset);
+        o = self.word(o, sh_size);
+        o = self.u32(o, sh_link);
+        o = self.u32(o, 0);
+        o = self.word(o, 1);
+        self.word(o, sh_entsize)
+    }
+}
+
+/// Builds a minimal dynamically linked executable for `class` and `endian`
+pub fn minimal_elf(class: Class, endian: Endian) -> Vec<u8> {
+    build_elf(class, endian, false)
+}
+
+/// Builds a minimal shared object for `class` and `endian`, an ET_DYN with DT_SONAME and without
+/// .interp or PT_INTERP.
+///
+/// .dynstr takes the place of .interp, so it starts at 0x100 instead of [`DYNSTR_OFFSET`].
+pub fn minimal_shared_object(class: Class, endian: Endian) -> Vec<u8> {
+    build_elf(class, endian, true)
+}
+
+fn build_elf(class: Class, endian: Endian, shared_object: bool) -> Vec<u8> {
+    let mut w = Writer {
+        class,
+        endian,
+        data: Vec::new(),
+    };
+
+    let (ehdr_size, phdr_size, shdr_size) = match class {
+        Class::Elf32 => (52, 32, 40),
+        Class::Elf64 => (64, 56, 64),
+    };
+    let e_machine = match (class, endian) {
+        (Class::Elf32, Endian::Little) => elf::abi::EM_386,
+        (Class::Elf32, Endian::Big) => elf::abi::EM_PPC,
+        (Class::Elf64, Endian::Little) => elf::abi::EM_X86_64,
+        (Class::Elf64, Endian::Big) => elf::abi::EM_S390,
+    };
+
+    let (dynstr, dynstr_offset) = if shared_object {
+        (
+            format!("\0__gmon_start__\0{}\0{}\0", NEEDED, SONAME),
+            INTERP_OFFSET,
+        )
+    } else {
+        (format!("\0__gmon_start__\0{}\0", NEEDED), DYNSTR_OFFSET)
+    };
+    let needed_offset = dynstr.find(NEEDED).unwrap() as u64;
+
+    if !shared_object {
+        let mut interp = INTERP.as_bytes().to_vec();
+        interp.resize(INTERP_SIZE, 0);
+        w.bytes(INTERP_OFFSET, &interp);
+    }
+    w.bytes(dynstr_offset, dynstr.as_bytes());
+
+    let mut dynamic = vec![
+        (elf::abi::DT_NEEDED, needed_offset),
+        (elf::abi::DT_STRTAB, dynstr_offset as u64),
+        (elf::abi::DT_STRSZ, dynstr.len() as u64),
+    ];
+    if shared_object {
+        dynamic.push((elf::abi::DT_SONAME, dynstr.find(SONAME).unwrap() as u64));
+    }
+    // Both end at the same offset, the shared object has one spare DT_NULL less
+    dynamic.resize(7, (elf::abi::DT_NULL, 0));
+    let mut o = DYNAMIC_OFFSET;
+    for (d_tag, d_val) in dynamic {
+        o = w.word(o, d_tag as u64);
+        o = w.word(o, d_val);
+    }
+    let dynamic_size = o - DYNAMIC_OFFSET;
+
+    let shstrtab = "\0.interp\0.dynstr\0.dynamic\0.shstrtab\0";
+    let shstrtab_offset = o;
+    let shoff = (w.bytes(shstrtab_offset, shstrtab.as_bytes()) + 7) & !7;
+    // The null section, .interp unless it is a shared object, .dynstr, .dynamic and .shstrtab
+    let shnum = if shared_object { 4 } else { 5 };
+    let file_size = shoff + shnum * shdr_size;
+
+    let mut o = ehdr_size;
+    o = w.phdr(o, elf::abi::PT_LOAD, 5, 0, file_size as u64, 0x1000);
+    if !shared_object {
+        o = w.phdr(
+            o,
+            elf::abi::PT_INTERP,
+            4,
+            INTERP_OFFSET as u64,
+            INTERP_SIZE as u64,
+            1,
+        );
+    }
+    o = w.phdr(
+        o,
+        elf::abi::PT_DYNAMIC,
+        6,
+        DYNAMIC_OFFSET as u64,
+        dynamic_size as u64,
+        w.word_size() as u64,
+    );
+    let phnum = (o - ehdr_size) / phdr_size;
+
+    let mut o = shoff + shdr_size;
+    if !shared_object {
+        o = w.shdr(
+            o,
+            1,
+            elf::abi::SHT_PROGBITS,
+            0x2,
+            INTERP_OFFSET as u64,
+            INTERP_SIZE as u64,
+            0,
+            0,
+        );
+    }
+    let dynstr_index = (o - shoff) / shdr_size;
+    o = w.shdr(
+        o,
+        9,
+        elf::abi::SHT_STRTAB,
+        0x2,
+        dynstr_offset as u64,
+        dynstr.len() as u64,
+        0,
+        0,
+    );
+    o = w.shdr(
+        o,
+        17,
+        elf::abi::SHT_DYNAMIC,
+        0x3,
+        DYNAMIC_OFFSET as u64,
+        dynamic_size as u64,
+        dynstr_index as u32,
+        (2 * w.word_size()) as u64,
+    );
+    w.shdr(
+        o,
+        26,
+        elf::abi::SHT_STRTAB,
+        0,
+        shstrtab_offset as u64,
+        shstrtab.len() as u64,
+        0,
+        0,
+    );
+
+    let ei_class = match class {
+        Class::Elf32 => elf::abi::ELFCLASS32,
+        Class::Elf64 => elf::abi::ELFCLASS64,
+    };
+    let ei_data = match endian {
+        Endian::Little => elf::abi::ELFDATA2LSB,
+        Endian::Big => elf::abi::ELFDATA2MSB,
+    };
+    w.bytes(0, &[0x7f, b'E', b'L', b'F', ei_class, ei_data, 1]);
+
+    let e_type = if shared_object {
+        elf::abi::ET_DYN
+    } else {
+        elf::abi::ET_EXEC
+    };
+    let mut o = w.u16(0x10, e_type);
+    o = w.u16(o, e_machine);
+    o = w.u32(o, 1);
+    o = w.word(o, 0);
+    o = w.word(o, ehdr_size as u64);
+    o = w.word(o, shoff as u64);
+    o = w.u32(o, 0);
+    o = w.u16(o, ehdr_size as u16);
+    o = w.u16(o, phdr_size as u16);
+    o = w.u16(o, phnum as u16);
+    o = w.u16(o, shdr_size as u16);
+    o = w.u16(o, shnum as u16);
+    w.u16(o, shnum as u16 - 1);
+
+    w.data
+}
+
+/// Writes a [`minimal_elf`] to `path`
+pub fn write_minimal_elf(path: &Path, class: Class, endian: Endian) {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).expect("Failed to create directory");
+    }
+    fs::write(path, minimal_elf(class, endian)).expect("Failed to write generated elf");
+}
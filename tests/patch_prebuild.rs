@@ -1,95 +1,2599 @@
-use patchelfdd::{opts::Opts, patchelfdd::Error, run};
+mod common;
 
-use std::{fs, path::PathBuf, process::Command};
+use patchelfdd::{
+    opts::{Opts, OptsBuilder},
+    patch::{self, DynamicStrategy, DynstrCandidate, Patcher, RpathKind, SacrificeReport},
+    patchelfdd::{Error, Status},
+    plan, profile, run, run_batch, sparse_elf,
+};
+use structopt::StructOpt;
+
+use std::{
+    fs,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, SystemTime},
+};
 
 const TEST_INTERPPATH: &str = "/lib-sus.so";
-const NATIVE_LIBC64: &str = "/lib64/libc.so.6";
-const NATIVE_LIBC32: &str = "/usr/lib32/libc.so.6";
 
 #[test]
 fn patch_minimal_amd64() -> Result<(), Error> {
-    test_prebuild_patch("./tests/prebuild/minimal-amd64", LIBC::ELF64)?;
-    Ok(())
+    test_patch(&scratch_copy("/tmp/elf64dd", "minimal-amd64"))
 }
 
 #[test]
 fn patch_minimal_i386() -> Result<(), Error> {
-    test_prebuild_patch("./tests/prebuild/minimal-i386", LIBC::ELF32)?;
+    // The generated 32-bit little endian elf is an EM_386 executable
+    let scratch_executable = PathBuf::from("/tmp/elf32dd/minimal-i386");
+    common::write_minimal_elf(
+        &scratch_executable,
+        common::Class::Elf32,
+        common::Endian::Little,
+    );
+    test_patch(&scratch_executable)
+}
+
+#[test]
+fn patch_itm_gprof_amd64() {
+    // The mcount and libitm.so guards reject both builtin candidates
+    match test_patch(&scratch_copy("/tmp/elf64dd", "itm-gprof-amd64")) {
+        Err(Error::PatchElf {
+            source:
+                patch::Error::NoDynstrReplacementCandidate {
+                    largest_candidate: 0,
+                    ..
+                },
+        }) => {}
+        other => panic!("Expected NoDynstrReplacementCandidate, got {:?}", other),
+    }
+}
+
+#[test]
+fn interpreter_path_must_leave_room_for_nul() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))?;
+    let interp_size = patcher.elf.shdr_interp.unwrap().sh_size as usize;
+
+    match patcher.set_interpreter_path(&"/".repeat(interp_size)) {
+        Err(patch::Error::CannotFitInterpreterPath { .. }) => {}
+        other => panic!("Expected CannotFitInterpreterPath, got {:?}", other),
+    }
+
+    let previous = patcher.set_interpreter_path(&"/".repeat(interp_size - 1))?;
+    assert_eq!(previous.as_deref(), Some("/lib64/ld-linux-x86-64.so.2"));
+
+    Ok(())
+}
+
+#[test]
+fn strict_refuses_relative_interpreter() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))?;
+
+    // Only a warning by default
+    patcher.set_interpreter_path("ld.so")?;
+    patcher.clear_patches();
+
+    patcher.set_strict(true);
+    assert!(matches!(
+        patcher.set_interpreter_path("ld.so"),
+        Err(patch::Error::SuspiciousValue { .. })
+    ));
+    assert!(patcher.is_empty());
+
     Ok(())
 }
 
 #[test]
-fn patch_itm_gprof_amd64() -> Result<(), String> {
-    match test_prebuild_patch("./tests/prebuild/itm-gprof-amd64", LIBC::ELF64) {
-        Ok(_) => Err("Should fail".to_string()),
-        Err(_) => Ok(()),
+fn nul_bytes_are_rejected() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))?;
+
+    let results = [
+        patcher
+            .set_runpath("foo\0bar", RpathKind::Runpath)
+            .map(|_| ()),
+        patcher.set_interpreter_path("foo\0bar").map(|_| ()),
+        patcher.set_soname("foo\0bar").map(|_| ()),
+        patcher.add_needed("foo\0bar"),
+    ];
+
+    for result in results {
+        assert!(matches!(
+            result,
+            Err(patch::Error::InvalidPathString { .. })
+        ));
     }
+    assert!(patcher.is_empty());
+
+    let dynstr_size = patcher.elf.shdr_dynstr.sh_size as usize;
+    assert!(matches!(
+        patcher.set_runpath(&"/".repeat(dynstr_size), RpathKind::Runpath),
+        Err(patch::Error::StringTooLong { .. })
+    ));
+
+    Ok(())
 }
 
-enum LIBC {
-    ELF32,
-    ELF64,
+#[test]
+fn missing_candidate_reports_largest_size() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))?;
+
+    // __gmon_start__ is the only candidate
+    match patcher.set_runpath(&"/".repeat(15), RpathKind::Runpath) {
+        Err(patch::Error::NoDynstrReplacementCandidate {
+            largest_candidate: 14,
+            requested_size: 15,
+        }) => {}
+        other => panic!("Expected NoDynstrReplacementCandidate, got {:?}", other),
+    }
+
+    Ok(())
 }
 
-fn setup(scratch_dir: &PathBuf, libc: LIBC) {
-    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
-    let local_libc = scratch_dir.join("libc.so.6");
-    match libc {
-        LIBC::ELF32 => {
-            fs::copy(NATIVE_LIBC32, local_libc).expect("Failed to copy native libc");
-        }
-        LIBC::ELF64 => {
-            fs::copy(NATIVE_LIBC64, local_libc).expect("Failed to copy native libc");
+#[test]
+fn list_candidates_skips_sacrificed_entries() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))?;
+    patcher.add_sacrifice_symbol("puts");
+
+    let gmon_start = DynstrCandidate {
+        symbol: "__gmon_start__".to_string(),
+        dynstr_offset: 0x1,
+        len: 14,
+    };
+    let puts = DynstrCandidate {
+        symbol: "puts".to_string(),
+        dynstr_offset: 0x1a,
+        len: 4,
+    };
+    assert_eq!(patcher.list_candidates()?, [gmon_start, puts.clone()]);
+
+    patcher.set_runpath("/new", RpathKind::Runpath)?;
+    assert_eq!(patcher.list_candidates()?, [puts]);
+
+    Ok(())
+}
+
+#[test]
+fn set_osabi_patches_the_header() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/osabidd", "minimal-amd64");
+
+    run(Opts {
+        set_osabi: Some(elf::abi::ELFOSABI_LINUX),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.osabi(), elf::abi::ELFOSABI_LINUX);
+
+    Ok(())
+}
+
+#[test]
+fn set_execstack_toggles_pf_x() -> Result<(), Error> {
+    for prebuild in ["minimal-amd64", "minimal-i386"] {
+        let scratch_executable = scratch_copy("/tmp/execstackdd", prebuild);
+
+        for execstack in [true, false] {
+            run(Opts {
+                set_execstack: Some(execstack),
+                ..opts_for(&scratch_executable)
+            })?;
+
+            let patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+            let stack = patcher.elf.segment(elf::abi::PT_GNU_STACK).unwrap();
+            assert_eq!(stack.p_flags & elf::abi::PF_X != 0, execstack);
+            assert_eq!(stack.p_flags & elf::abi::PF_R, elf::abi::PF_R);
         }
     }
+
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-s390x"))
+        .expect("Failed to parse elf");
+    assert!(matches!(
+        patcher.set_execstack(true),
+        Err(patch::Error::NoGnuStackSegment)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn preserve_timestamps_keeps_mtime() -> Result<(), Error> {
+    for atomic in [false, true] {
+        let scratch_executable =
+            scratch_copy(&format!("/tmp/timestampsdd/{}", atomic), "minimal-amd64");
+
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&scratch_executable)
+            .and_then(|f| f.set_modified(mtime))
+            .expect("Failed to set mtime");
+
+        run(Opts {
+            set_interpreter: Some("/ld.so".to_string()),
+            preserve_timestamps: true,
+            atomic,
+            ..opts_for(&scratch_executable)
+        })?;
+
+        let metadata = fs::metadata(&scratch_executable).expect("Failed to stat executable");
+        assert_eq!(metadata.modified().unwrap(), mtime);
+    }
+
+    Ok(())
 }
 
-fn verify_patches_with_ldd(executable_path: &PathBuf, scratch_dir: &str) {
-    let output = Command::new("ldd")
-        .arg(executable_path)
+#[test]
+fn queries_keep_stdout_clean() {
+    let output = Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+        .args(["--bin", "./tests/prebuild/minimal-amd64", "--verbose"])
+        .args(["--print-interpreter", "--print-runpath"])
         .output()
-        .expect("Faild to run ldd");
+        .expect("Failed to run patchelfdd");
 
     assert!(output.status.success());
-    let output_string = String::from_utf8_lossy(&output.stdout).into_owned();
-    let output_lines = output_string.lines();
-
-    let mut correct_runpath: bool = false;
-    let mut correct_interppath: bool = false;
-    for (_, line) in output_lines.enumerate() {
-        dbg!(line);
-        if line.contains(&format!("{}/libc.so.6", scratch_dir)) {
-            correct_runpath = true;
-        } else if line.contains(TEST_INTERPPATH) {
-            correct_interppath = true;
-        }
-    }
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "/lib64/ld-linux-x86-64.so.2\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No runpath set"));
+}
+
+#[test]
+fn shared_object_without_interp_is_patched() -> Result<(), Error> {
+    let scratch_library = PathBuf::from("/tmp/sharedobjectdd/libminimal.so.1");
+    fs::create_dir_all(scratch_library.parent().unwrap()).expect("Failed to create directory");
+    fs::write(
+        &scratch_library,
+        common::minimal_shared_object(common::Class::Elf64, common::Endian::Little),
+    )
+    .expect("Failed to write generated elf");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+        .arg("--bin")
+        .arg(&scratch_library)
+        .arg("--print-soname")
+        .output()
+        .expect("Failed to run patchelfdd");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\n", common::SONAME)
+    );
 
-    assert!(correct_runpath);
-    assert!(correct_interppath);
+    run(Opts {
+        set_soname: Some("libother.so.2".to_string()),
+        ..opts_for(&scratch_library)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_library).expect("Failed to parse patched elf");
+    assert_eq!(
+        patcher.elf.soname().unwrap().as_deref(),
+        Some("libother.so.2")
+    );
+    assert!(!patcher.elf.is_pie().unwrap());
+
+    // Only the interpreter operations need .interp
+    assert!(matches!(
+        patcher.elf.interpreter(),
+        Err(sparse_elf::Error::NoInterpSection)
+    ));
+    assert!(matches!(
+        patcher.set_interpreter_path(TEST_INTERPPATH),
+        Err(patch::Error::SparseElf {
+            source: sparse_elf::Error::NoInterpSection
+        })
+    ));
+
+    Ok(())
 }
 
-fn test_prebuild_patch(prebuild_path: &str, libc: LIBC) -> Result<(), Error> {
-    let path = PathBuf::from(prebuild_path);
-    let scratch_dir = PathBuf::from(match libc {
-        LIBC::ELF32 => "/tmp/elf32dd",
-        LIBC::ELF64 => "/tmp/elf64dd",
-    });
+#[test]
+fn runpath_entries_are_split() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/runpathentriesdd", "minimal-amd64");
 
-    setup(&scratch_dir, libc);
+    run(Opts {
+        set_runpath: Some("/a:$ORIGIN/b".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
 
-    let file_name = path.file_name().expect("Failed to get executable name");
-    let scratch_executable = scratch_dir.join(file_name);
-    fs::copy(path, &scratch_executable).expect("Failed to copy executable to tmpdir");
-    let opts = Opts {
-        bin: scratch_executable.clone(),
-        set_runpath: Some(scratch_dir.to_string_lossy().to_string()),
-        set_interpreter: Some(TEST_INTERPPATH.to_string()),
+    let print_entries = |format: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+            .arg("--bin")
+            .arg(&scratch_executable)
+            .args(["--print-rpath-entries", "--format", format])
+            .output()
+            .expect("Failed to run patchelfdd");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).to_string()
     };
 
-    run(opts)?;
+    assert_eq!(print_entries("human"), "/a\n$ORIGIN/b\n");
+    assert_eq!(
+        print_entries("json"),
+        "{\"runpath_entries\":[\"/a\",\"$ORIGIN/b\"]}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn print_runpath_shows_runpath_or_rpath() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/printrunpathdd", "minimal-amd64");
+    assert_eq!(query(&scratch_executable, &["--print-runpath"]), "");
+
+    run(Opts {
+        set_rpath: Some("/opt/rpath".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+    assert_eq!(
+        query(&scratch_executable, &["--print-runpath"]),
+        "/opt/rpath\n"
+    );
+    assert_eq!(
+        query(
+            &scratch_executable,
+            &["--print-runpath", "--format", "json"]
+        ),
+        "{\"runpath\":\"/opt/rpath\"}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn set_rpath_writes_dt_rpath() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/setrpathdd", "minimal-amd64");
+
+    run(Opts {
+        set_rpath: Some("/opt/rpath".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(
+        patcher
+            .elf
+            .dynamic_string(elf::abi::DT_RPATH)
+            .unwrap()
+            .as_deref(),
+        Some("/opt/rpath")
+    );
+    assert!(!patcher.elf.dynamic_contains(elf::abi::DT_RUNPATH).unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn force_overwrites_the_runpath() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/forcedd", "minimal-amd64");
+
+    run(Opts {
+        set_runpath: Some("/old".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+    assert!(matches!(
+        run(Opts {
+            set_runpath: Some("/new".to_string()),
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::RunpathAlreadySet {
+            kind: RpathKind::Runpath
+        })
+    ));
+
+    run(Opts {
+        set_runpath: Some("/new".to_string()),
+        force: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpaths().unwrap(), ["/new"]);
+
+    Ok(())
+}
+
+#[test]
+fn print_needed_lists_the_libraries() {
+    let bin = Path::new("./tests/prebuild/minimal-amd64");
+
+    assert_eq!(query(bin, &["--print-needed"]), "libc.so.6\n");
+    assert_eq!(
+        query(bin, &["--print-needed", "--format", "json"]),
+        "{\"needed\":[\"libc.so.6\"]}\n"
+    );
+}
+
+#[test]
+fn remove_runpath_drops_the_entry() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/removerunpathdd", "minimal-amd64");
+
+    run(Opts {
+        set_runpath: Some("/old".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+    run(Opts {
+        remove_runpath: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap(), None);
+    assert!(!patcher.elf.dynamic_contains(elf::abi::DT_RUNPATH).unwrap());
+    drop(patcher);
+
+    assert!(matches!(
+        run(Opts {
+            remove_runpath: true,
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::PatchElf {
+            source: patch::Error::NoRunpathToRemove
+        })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn add_needed_appends_a_library() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/addneededdd", "minimal-amd64");
+
+    run(Opts {
+        add_needed: vec!["libextra.so.1".to_string()],
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(
+        patcher.elf.needed().unwrap(),
+        ["libc.so.6", "libextra.so.1"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn remove_needed_compacts_dynamic() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/removeneededdd", "minimal-amd64");
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse elf");
+    let entries_before: Vec<i64> = patcher
+        .elf
+        .dynamic()
+        .unwrap()
+        .iter()
+        .map(|d| d.d_tag)
+        .collect();
+    drop(patcher);
+
+    run(Opts {
+        remove_needed: Some("libc.so.6".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    // The entries after DT_NEEDED move up by one and DT_NULL fills the freed slot
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    let entries_after: Vec<i64> = patcher
+        .elf
+        .dynamic()
+        .unwrap()
+        .iter()
+        .map(|d| d.d_tag)
+        .collect();
+    let mut expected: Vec<i64> = entries_before
+        .into_iter()
+        .filter(|d_tag| *d_tag != elf::abi::DT_NEEDED)
+        .collect();
+    expected.push(elf::abi::DT_NULL);
+    assert_eq!(entries_after, expected);
+    assert!(patcher.elf.needed().unwrap().is_empty());
+    drop(patcher);
+
+    assert!(matches!(
+        run(Opts {
+            remove_needed: Some("libc.so.6".to_string()),
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::PatchElf {
+            source: patch::Error::NeededNotFound { library }
+        }) if library == "libc.so.6"
+    ));
 
-    verify_patches_with_ldd(&scratch_executable, &scratch_dir.to_string_lossy());
+    Ok(())
+}
+
+#[test]
+fn replace_needed_swaps_the_library() -> Result<(), Error> {
+    // A name that fits is written over the old one
+    let scratch_executable = scratch_copy("/tmp/replaceneededdd", "minimal-amd64");
+    run(Opts {
+        replace_needed: Some(vec!["libc.so.6".to_string(), "libc.so.7".to_string()]),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.needed().unwrap(), ["libc.so.7"]);
+    assert!(patcher.elf.dynstr_contains("__gmon_start__").unwrap());
+    drop(patcher);
+
+    // A longer one needs a sacrificed entry
+    run(Opts {
+        replace_needed: Some(vec!["libc.so.7".to_string(), "libcompat.so.6".to_string()]),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.needed().unwrap(), ["libcompat.so.6"]);
+    assert!(!patcher.elf.dynstr_contains("__gmon_start__").unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn replace_needed_keeps_merged_suffixes() -> Result<(), patch::Error> {
+    let scratch_dir = PathBuf::from("/tmp/replacemergeddd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("merged-suffix-amd64");
+
+    // A second DT_NEEDED in the first spare slot names c.so.6, the tail of libc.so.6
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+    let entry_offset = common::DYNAMIC_OFFSET + 3 * 16;
+    data[entry_offset..entry_offset + 8]
+        .copy_from_slice(&(elf::abi::DT_NEEDED as u64).to_le_bytes());
+    data[entry_offset + 8..entry_offset + 16].copy_from_slice(&19u64.to_le_bytes());
+    fs::write(&scratch_executable, &data).expect("Failed to write elf");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    assert_eq!(patcher.elf.needed().unwrap(), [common::NEEDED, "c.so.6"]);
+
+    // libd.so.6 would fit, but writing it over libc.so.6 would turn c.so.6 into d.so.6
+    assert_eq!(patcher.replace_needed(common::NEEDED, "libd.so.6")?, 1);
+    assert_eq!(patcher.sacrifices()[0].symbol, "__gmon_start__");
+    patcher.apply()?;
+    drop(patcher);
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    assert_eq!(patcher.elf.needed().unwrap(), ["libd.so.6", "c.so.6"]);
+
+    Ok(())
+}
+
+#[test]
+fn set_soname_keeps_merged_suffixes() -> Result<(), patch::Error> {
+    let scratch_dir = PathBuf::from("/tmp/sonamemergeddd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_library = scratch_dir.join("merged-suffix-amd64.so");
+
+    // A DT_NEEDED in the first spare slot names minimal.so.1, the tail of libminimal.so.1
+    let mut data = common::minimal_shared_object(common::Class::Elf64, common::Endian::Little);
+    let soname_offset = format!("\0__gmon_start__\0{}\0", common::NEEDED).len() as u64;
+    let entry_offset = common::DYNAMIC_OFFSET + 4 * 16;
+    data[entry_offset..entry_offset + 8]
+        .copy_from_slice(&(elf::abi::DT_NEEDED as u64).to_le_bytes());
+    data[entry_offset + 8..entry_offset + 16].copy_from_slice(&(soname_offset + 3).to_le_bytes());
+    fs::write(&scratch_library, &data).expect("Failed to write elf");
+
+    let mut patcher = Patcher::new(&scratch_library)?;
+    assert_eq!(
+        patcher.elf.needed().unwrap(),
+        [common::NEEDED, "minimal.so.1"]
+    );
+
+    // libother.so.1 would fit, but writing it over the soname would change minimal.so.1
+    patcher.set_soname("libother.so.1")?;
+    assert_eq!(patcher.sacrifices()[0].symbol, "__gmon_start__");
+    patcher.apply()?;
+    drop(patcher);
+
+    let mut patcher = Patcher::new(&scratch_library)?;
+    assert_eq!(
+        patcher.elf.soname().unwrap().as_deref(),
+        Some("libother.so.1")
+    );
+    assert_eq!(
+        patcher.elf.needed().unwrap(),
+        [common::NEEDED, "minimal.so.1"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn backup_keeps_the_original() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/backupdd", "minimal-amd64");
+    let original = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    run(Opts {
+        set_interpreter: Some(TEST_INTERPPATH.to_string()),
+        backup: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let backup = scratch_executable.with_file_name("minimal-amd64.orig");
+    assert_eq!(fs::read(backup).expect("Failed to read backup"), original);
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+
+    Ok(())
+}
+
+#[test]
+fn sacrifice_symbol_adds_a_candidate() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/sacrificesymboldd", "minimal-amd64");
+    // Longer than __gmon_start__, but fits __libc_start_main
+    let runpath = "/opt/sixteen/lib";
+
+    assert!(matches!(
+        run(Opts {
+            set_runpath: Some(runpath.to_string()),
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::PatchElf {
+            source: patch::Error::NoDynstrReplacementCandidate { .. }
+        })
+    ));
+
+    run(Opts {
+        set_runpath: Some(runpath.to_string()),
+        sacrifice_symbol: vec!["__libc_start_main".to_string()],
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some(runpath));
+    assert!(!patcher.elf.dynstr_contains("__libc_start_main").unwrap());
+    assert!(patcher.elf.dynstr_contains("__gmon_start__").unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn sacrifice_offset_picks_the_entry() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/sacrificeoffsetdd", "minimal-amd64");
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse elf");
+    let puts_offset = patcher
+        .elf
+        .dynstr_entries()
+        .unwrap()
+        .into_iter()
+        .find(|(_, entry)| *entry == "puts")
+        .unwrap()
+        .0;
+    drop(patcher);
+
+    run(Opts {
+        set_runpath: Some("/opt".to_string()),
+        sacrifice_offset: Some(puts_offset),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.dynstr_at(puts_offset).unwrap(), "/opt");
+    assert_eq!(
+        patcher.elf.dynamic_value(elf::abi::DT_RUNPATH).unwrap(),
+        Some(puts_offset as u64)
+    );
+    assert!(patcher.elf.dynstr_contains("__gmon_start__").unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn notable_dynamic_tags_are_listed() {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))
+        .expect("Failed to parse elf");
+    assert_eq!(
+        patcher.elf.notable_dynamic_tags().unwrap(),
+        ["DT_DEBUG", "DT_HASH", "DT_GNU_HASH"]
+    );
+
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/itm-gprof-amd64"))
+        .expect("Failed to parse elf");
+    assert_eq!(
+        patcher.elf.notable_dynamic_tags().unwrap(),
+        ["DT_DEBUG", "DT_GNU_HASH"]
+    );
+}
+
+#[test]
+fn candidate_safety_can_be_ignored() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/itm-gprof-amd64"))?;
+    assert!(patcher.list_candidates()?.is_empty());
+
+    patcher.set_ignore_candidate_safety(true);
+    let symbols: Vec<String> = patcher
+        .list_candidates()?
+        .into_iter()
+        .map(|candidate| candidate.symbol)
+        .collect();
+    let builtin_symbols: Vec<&str> = patch::BUILTIN_CANDIDATES
+        .iter()
+        .map(|candidate| candidate.symbol)
+        .collect();
+    assert_eq!(symbols, builtin_symbols);
+
+    patcher.set_runpath("/opt/gprof", RpathKind::Runpath)?;
+    assert_eq!(patcher.sacrifices()[0].symbol, "__gmon_start__");
+
+    Ok(())
+}
+
+#[test]
+fn weak_symbols_count_as_relocation_targets() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))?;
+
+    // __gmon_start__ is an undefined weak symbol with a R_X86_64_GLOB_DAT relocation
+    let entries = patcher.elf.dynstr_entries().unwrap();
+    let offset_of = |name: &str| entries.iter().find(|(_, entry)| *entry == name).unwrap().0;
+    let (gmon_start, libc) = (offset_of("__gmon_start__"), offset_of("libc.so.6"));
+    assert!(patcher.elf.is_relocation_target(gmon_start).unwrap());
+    assert!(!patcher.elf.is_relocation_target(libc).unwrap());
+
+    patcher.set_safe(true);
+    assert!(matches!(
+        patcher.set_runpath("/opt/safe", RpathKind::Runpath),
+        Err(patch::Error::SacrificedSymbolRelocated { symbol }) if symbol == "__gmon_start__"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn relocations_are_found_without_section_headers() -> Result<(), patch::Error> {
+    let scratch_dir = PathBuf::from("/tmp/nosectionsrelocdd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("minimal-amd64");
+
+    // Clear e_shoff, e_shnum and e_shstrndx of the ELF64 header
+    let mut data = fs::read("./tests/prebuild/minimal-amd64").expect("Failed to read executable");
+    data[0x28..0x30].fill(0);
+    data[0x3c..0x40].fill(0);
+    fs::write(&scratch_executable, data).expect("Failed to write executable");
+
+    // .rela.dyn and .rela.plt are found through DT_RELA and DT_JMPREL, .dynsym through DT_SYMTAB
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    let entries = patcher.elf.dynstr_entries().unwrap();
+    let offset_of = |name: &str| entries.iter().find(|(_, entry)| *entry == name).unwrap().0;
+    let (gmon_start, puts, libc) = (
+        offset_of("__gmon_start__"),
+        offset_of("puts"),
+        offset_of("libc.so.6"),
+    );
+    assert!(patcher.elf.is_relocation_target(gmon_start).unwrap());
+    assert!(patcher.elf.is_relocation_target(puts).unwrap());
+    assert!(!patcher.elf.is_relocation_target(libc).unwrap());
+
+    patcher.set_safe(true);
+    assert!(matches!(
+        patcher.set_runpath("/opt/safe", RpathKind::Runpath),
+        Err(patch::Error::SacrificedSymbolRelocated { symbol }) if symbol == "__gmon_start__"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn only_planned_bytes_change() -> Result<(), patch::Error> {
+    let scratch_executable = scratch_copy("/tmp/unrelateddd", "minimal-amd64");
+    let original = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.set_runpath("/opt/lib", RpathKind::Runpath)?;
+    patcher.set_interpreter_path(TEST_INTERPPATH)?;
+
+    let planned_ranges: Vec<std::ops::Range<usize>> = patcher
+        .planned_patches()
+        .iter()
+        .map(|patch| patch.offset()..patch.offset() + patch.data().len())
+        .collect();
+    patcher.apply()?;
+
+    let patched = fs::read(&scratch_executable).expect("Failed to read executable");
+    assert_eq!(patched.len(), original.len());
+
+    let changed_offsets: Vec<usize> = (0..original.len())
+        .filter(|&offset| original[offset] != patched[offset])
+        .collect();
+    assert!(!changed_offsets.is_empty());
+    for offset in changed_offsets {
+        assert!(
+            planned_ranges.iter().any(|range| range.contains(&offset)),
+            "Byte at {:#x} changed outside of the planned patches",
+            offset
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn interpreter_is_written_where_pt_interp_points() -> Result<(), patch::Error> {
+    let scratch_dir = PathBuf::from("/tmp/splitinterpdd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("split-interp-i386");
+
+    // Point PT_INTERP at a copy of the path in the gap after the program headers, so it
+    // disagrees with .interp at 0x100
+    let mut data = common::minimal_elf(common::Class::Elf32, common::Endian::Little);
+    let interp_copy_offset = 0xa0;
+    data[interp_copy_offset..interp_copy_offset + common::INTERP.len()]
+        .copy_from_slice(common::INTERP.as_bytes());
+    // p_offset of the second Elf32_Phdr, after the 52 byte header and the PT_LOAD
+    data[52 + 32 + 4..52 + 32 + 8].copy_from_slice(&(interp_copy_offset as u32).to_le_bytes());
+    fs::write(&scratch_executable, &data).expect("Failed to write elf");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.set_interpreter_path(TEST_INTERPPATH)?;
+    patcher.apply()?;
+
+    let patched = fs::read(&scratch_executable).expect("Failed to read elf");
+    for interp_offset in [interp_copy_offset, 0x100] {
+        assert_eq!(
+            &patched[interp_offset..interp_offset + TEST_INTERPPATH.len() + 1],
+            format!("{}\0", TEST_INTERPPATH).as_bytes()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn forced_class_sets_the_field_width() -> Result<(), patch::Error> {
+    // An Elf64 header, but .dynamic holds Elf32_Dyn entries
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+    let elf32 = common::minimal_elf(common::Class::Elf32, common::Endian::Little);
+    let (dynamic32, dynamic64) = (7 * 8, 7 * 16);
+    data[common::DYNAMIC_OFFSET..common::DYNAMIC_OFFSET + dynamic64].fill(0);
+    data[common::DYNAMIC_OFFSET..common::DYNAMIC_OFFSET + dynamic32]
+        .copy_from_slice(&elf32[common::DYNAMIC_OFFSET..common::DYNAMIC_OFFSET + dynamic32]);
+
+    let scratch_executable = PathBuf::from("/tmp/forceclassdd/minimal");
+    fs::create_dir_all(scratch_executable.parent().unwrap()).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.set_force_class(elf::file::Class::ELF32);
+    assert_eq!(patcher.serializer().word_size(), 4);
+    assert_eq!(patcher.elf.needed().unwrap(), [common::NEEDED]);
+    assert_eq!(patcher.elf.dynamic_stats().unwrap().entries, 14);
+
+    patcher.set_runpath("/opt/lib", RpathKind::Runpath)?;
+    patcher.apply()?;
+    drop(patcher);
+
+    // __gmon_start__ is overwritten and the first DT_NULL, the fourth Elf32_Dyn, is the runpath
+    let mut expected = data;
+    let dynstr_entry = common::DYNSTR_OFFSET + 1;
+    expected[dynstr_entry..dynstr_entry + 9].copy_from_slice(b"/opt/lib\0");
+    let dyn_entry = common::DYNAMIC_OFFSET + 3 * 8;
+    expected[dyn_entry..dyn_entry + 8].copy_from_slice(&[29, 0, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        expected
+    );
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.set_force_class(elf::file::Class::ELF32);
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/opt/lib"));
+
+    Ok(())
+}
+
+#[test]
+fn forced_layout_leaves_the_headers_alone() -> Result<(), patch::Error> {
+    let expected_executable = scratch_copy("/tmp/forceheadersdd", "minimal-amd64");
+    let scratch_executable = expected_executable.with_file_name("forced-amd64");
+    fs::copy(&expected_executable, &scratch_executable).expect("Failed to copy executable");
+
+    let mut patcher = Patcher::new(&expected_executable)?;
+    patcher.set_execstack(true)?;
+    patcher.apply()?;
+    drop(patcher);
+
+    // p_flags is still written where and how the Elf64 little endian header places it
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.set_force_class(elf::file::Class::ELF32);
+    patcher.set_force_endianness(elf::endian::AnyEndian::Big);
+    patcher.set_execstack(true)?;
+    patcher.apply()?;
+    drop(patcher);
+
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        fs::read(&expected_executable).expect("Failed to read executable")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn forced_endianness_sets_the_byte_order() -> Result<(), patch::Error> {
+    // A little endian header, but .dynamic is big endian
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+    let big_endian = common::minimal_elf(common::Class::Elf64, common::Endian::Big);
+    let dynamic = common::DYNAMIC_OFFSET..common::DYNAMIC_OFFSET + 7 * 16;
+    data[dynamic.clone()].copy_from_slice(&big_endian[dynamic]);
+
+    let scratch_executable = PathBuf::from("/tmp/forceendiandd/minimal");
+    fs::create_dir_all(scratch_executable.parent().unwrap()).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.set_force_endianness(elf::endian::AnyEndian::Big);
+    assert_eq!(patcher.endianness(), elf::endian::AnyEndian::Big);
+    assert_eq!(patcher.elf.needed().unwrap(), [common::NEEDED]);
+
+    patcher.set_runpath("/opt/lib", RpathKind::Runpath)?;
+    patcher.apply()?;
+    drop(patcher);
+
+    let mut expected = data;
+    let dynstr_entry = common::DYNSTR_OFFSET + 1;
+    expected[dynstr_entry..dynstr_entry + 9].copy_from_slice(b"/opt/lib\0");
+    let dyn_entry = common::DYNAMIC_OFFSET + 3 * 16;
+    expected[dyn_entry..dyn_entry + 8].copy_from_slice(&29u64.to_be_bytes());
+    expected[dyn_entry + 8..dyn_entry + 16].copy_from_slice(&1u64.to_be_bytes());
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        expected
+    );
 
     Ok(())
 }
+
+#[test]
+fn raw_bytes_are_read_at_an_offset() {
+    let elf = sparse_elf::SparseElf::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))
+        .expect("Failed to parse elf");
+    let file_size = fs::metadata("./tests/prebuild/minimal-amd64")
+        .unwrap()
+        .len();
+
+    assert_eq!(elf.raw_bytes_at(0, 4).unwrap(), b"\x7fELF");
+    assert_eq!(elf.raw_bytes_at(file_size - 2, 2).unwrap().len(), 2);
+    assert!(elf.raw_bytes_at(file_size, 0).unwrap().is_empty());
+
+    match elf.raw_bytes_at(file_size - 2, 4) {
+        Err(sparse_elf::Error::ReadPastEnd { len: 4, .. }) => {}
+        other => panic!(
+            "Expected ReadPastEnd, got {:?}",
+            other.map(|data| data.len())
+        ),
+    }
+    assert!(matches!(
+        elf.raw_bytes_at(u64::MAX, 1),
+        Err(sparse_elf::Error::ReadPastEnd { .. })
+    ));
+}
+
+#[test]
+fn runpath_separators_are_normalized() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/normalizedd", "minimal-s390x");
+
+    // Normalized to 13 bytes, so it fits the 14 of __gmon_start__
+    run(Opts {
+        set_runpath: Some("/opt/a,/opt/b::/opt/a:".to_string()),
+        verify: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(
+        patcher.elf.runpath().unwrap().as_deref(),
+        Some("/opt/a:/opt/b")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn check_reports_the_first_problem() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/checkdd", "minimal-s390x");
+    let original = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    let status = run(Opts {
+        set_runpath: Some("/opt/be".to_string()),
+        check: true,
+        ..opts_for(&scratch_executable)
+    })?;
+    assert_eq!(status, Status::Checked);
+
+    // .interp of the s390x binary holds 16 bytes
+    match run(Opts {
+        set_runpath: Some("/opt/be".to_string()),
+        set_interpreter: Some("/lib/ld64-much-too-long.so.1".to_string()),
+        check: true,
+        ..opts_for(&scratch_executable)
+    }) {
+        Err(Error::PatchElf {
+            source: patch::Error::CannotFitInterpreterPath { .. },
+        }) => {}
+        other => panic!("Expected CannotFitInterpreterPath, got {:?}", other),
+    }
+
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        original
+    );
+
+    Ok(())
+}
+
+#[test]
+fn multiple_runpaths_need_consolidation() -> Result<(), Error> {
+    let scratch_dir = PathBuf::from("/tmp/multirunpathdd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("two-runpaths-amd64");
+
+    // Two DT_RUNPATH entries in the first spare slots, pointing at __gmon_start__ and libc.so.6
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+    for (slot, d_val) in [(3, 1u64), (4, 16)] {
+        let entry_offset = common::DYNAMIC_OFFSET + slot * 16;
+        data[entry_offset..entry_offset + 8]
+            .copy_from_slice(&(elf::abi::DT_RUNPATH as u64).to_le_bytes());
+        data[entry_offset + 8..entry_offset + 16].copy_from_slice(&d_val.to_le_bytes());
+    }
+    fs::write(&scratch_executable, &data).expect("Failed to write elf");
+
+    let mut elf = sparse_elf::SparseElf::new(&scratch_executable).expect("Failed to parse elf");
+    assert_eq!(elf.runpaths().unwrap(), ["__gmon_start__", common::NEEDED]);
+    assert_eq!(elf.runpath().unwrap().as_deref(), Some("__gmon_start__"));
+
+    let opts = Opts {
+        set_runpath: Some("/new".to_string()),
+        force: true,
+        ..opts_for(&scratch_executable)
+    };
+    match run(opts.clone()) {
+        Err(Error::MultipleRunpaths { count: 2 }) => {}
+        other => panic!("Expected MultipleRunpaths, got {:?}", other),
+    }
+
+    run(Opts {
+        consolidate_runpath: true,
+        ..opts
+    })?;
+
+    let mut elf = sparse_elf::SparseElf::new(&scratch_executable).expect("Failed to parse elf");
+    assert_eq!(elf.runpaths().unwrap(), ["/new"]);
+
+    Ok(())
+}
+
+#[test]
+fn quiet_only_prints_errors() {
+    let scratch_executable = scratch_copy("/tmp/quietdd", "minimal-amd64");
+
+    let patch = |runpath: &str| {
+        Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+            .arg("--bin")
+            .arg(&scratch_executable)
+            .args(["--quiet", "--set-runpath", runpath])
+            .output()
+            .expect("Failed to run patchelfdd")
+    };
+
+    // Sacrificing __gmon_start__ usually warns
+    let output = patch("/opt/lib");
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = patch("/opt/other");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already set"));
+}
+
+#[test]
+fn pie_is_detected() {
+    for (path, pie) in [
+        ("./tests/prebuild/itm-gprof-amd64", true),
+        ("./tests/prebuild/minimal-amd64", false),
+        ("./tests/prebuild/minimal-s390x", true),
+    ] {
+        let mut elf =
+            sparse_elf::SparseElf::new(&PathBuf::from(path)).expect("Failed to parse elf");
+        assert_eq!(elf.is_pie().unwrap(), pie, "{}", path);
+    }
+
+    let scratch_dir = PathBuf::from("/tmp/piedd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_library = scratch_dir.join("library-amd64");
+
+    // An ET_DYN without PT_INTERP is a shared object, unless DF_1_PIE says otherwise
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+    data[0x10..0x12].copy_from_slice(&elf::abi::ET_DYN.to_le_bytes());
+    // p_type of the PT_INTERP header, after the 64 byte header and the PT_LOAD
+    data[64 + 56..64 + 56 + 4].copy_from_slice(&elf::abi::PT_NULL.to_le_bytes());
+    fs::write(&scratch_library, &data).expect("Failed to write elf");
+
+    let mut patcher = Patcher::new(&scratch_library).expect("Failed to parse elf");
+    assert!(!patcher.elf.is_pie().unwrap());
+
+    patcher
+        .update_flags_1(elf::abi::DF_1_PIE as u64, 0)
+        .unwrap();
+    patcher.apply().unwrap();
+
+    let mut elf = sparse_elf::SparseElf::new(&scratch_library).expect("Failed to parse elf");
+    assert!(elf.is_pie().unwrap());
+}
+
+#[test]
+fn machine_filter_skips_other_machines() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/machinefilterdd", "minimal-s390x");
+    let original = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    let status = run(Opts {
+        set_runpath: Some("/opt/be".to_string()),
+        machine_filter: Some(elf::abi::EM_X86_64),
+        ..opts_for(&scratch_executable)
+    })?;
+    assert_eq!(status, Status::Skipped);
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        original
+    );
+
+    let status = run(Opts {
+        set_runpath: Some("/opt/be".to_string()),
+        machine_filter: Some(elf::abi::EM_S390),
+        ..opts_for(&scratch_executable)
+    })?;
+    assert_eq!(status, Status::Patched);
+
+    Ok(())
+}
+
+#[test]
+fn batch_keeps_going_after_a_failure() {
+    let scratch_executable = scratch_copy("/tmp/batchdd", "minimal-amd64");
+    let missing = scratch_executable.with_file_name("missing");
+
+    let summary = run_batch(Opts {
+        set_runpath: Some("/opt/batch".to_string()),
+        force: true,
+        more_bins: vec![scratch_executable.clone()],
+        ..opts_for(&missing)
+    });
+
+    assert!(!summary.all_succeeded());
+    assert!(!summary.all_failed());
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].0, missing);
+    assert_eq!(
+        summary.succeeded,
+        vec![(scratch_executable.clone(), Status::Patched)]
+    );
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(
+        patcher.elf.runpath().unwrap().as_deref(),
+        Some("/opt/batch")
+    );
+}
+
+#[test]
+fn preferred_candidate_is_sacrificed_first() -> Result<(), patch::Error> {
+    let sacrificed =
+        |preferred: Option<&str>, fallback: bool, runpath: &str| -> Result<String, patch::Error> {
+            let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/itm-gprof-amd64"))?;
+            patcher.set_ignore_candidate_safety(true);
+            patcher.set_preferred_candidate(preferred.map(str::to_string));
+            patcher.set_allow_candidate_fallback(fallback);
+            patcher.set_runpath(runpath, RpathKind::Runpath)?;
+            Ok(patcher.sacrifices()[0].symbol.clone())
+        };
+
+    assert_eq!(sacrificed(None, false, "/opt/lib")?, "__gmon_start__");
+    assert_eq!(
+        sacrificed(Some("_ITM_deregisterTMCloneTable"), false, "/opt/lib")?,
+        "_ITM_deregisterTMCloneTable"
+    );
+
+    // A preferred candidate that is too small or absent is an error, unless falling back to the
+    // usual search is allowed
+    assert!(matches!(
+        sacrificed(Some("__gmon_start__"), false, "/opt/a/much/longer/lib"),
+        Err(patch::Error::PreferredCandidateUnusable { symbol, requested_size: 22 })
+            if symbol == "__gmon_start__"
+    ));
+    assert!(matches!(
+        sacrificed(Some("missing"), false, "/opt/lib"),
+        Err(patch::Error::PreferredCandidateUnusable { .. })
+    ));
+    assert_eq!(
+        sacrificed(Some("__gmon_start__"), true, "/opt/a/much/longer/lib")?,
+        "_ITM_deregisterTMCloneTable"
+    );
+    assert_eq!(
+        sacrificed(Some("missing"), true, "/opt/lib")?,
+        "__gmon_start__"
+    );
+
+    // Later strings use other candidates once the preferred one is sacrificed
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/itm-gprof-amd64"))?;
+    patcher.set_ignore_candidate_safety(true);
+    patcher.set_preferred_candidate(Some("__gmon_start__".to_string()));
+    patcher.set_runpath("/opt/lib", RpathKind::Runpath)?;
+    patcher.add_needed("libextra.so")?;
+    let symbols: Vec<&str> = patcher
+        .sacrifices()
+        .iter()
+        .map(|sacrifice| sacrifice.symbol.as_str())
+        .collect();
+    assert_eq!(symbols, ["__gmon_start__", "_ITM_deregisterTMCloneTable"]);
+
+    Ok(())
+}
+
+#[test]
+fn patch_through_an_open_file() -> Result<(), patch::Error> {
+    let scratch_executable = scratch_copy("/tmp/openfiledd", "minimal-amd64");
+
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&scratch_executable)
+        .expect("Failed to open executable");
+
+    let mut patcher = Patcher::from_file(file, "minimal-amd64", false)?;
+    patcher.set_interpreter_path(TEST_INTERPPATH)?;
+    patcher.apply()?;
+
+    let file = fs::File::open(&scratch_executable).expect("Failed to open executable");
+    let mut elf = sparse_elf::SparseElf::from_file(file, false).expect("Failed to parse elf");
+    assert_eq!(elf.interpreter().unwrap(), TEST_INTERPPATH);
+
+    Ok(())
+}
+
+#[test]
+fn opening_waits_for_the_lock() {
+    let scratch_executable = scratch_copy("/tmp/lockdd", "minimal-amd64");
+
+    // Without the lock the elf is parsed right away, while another process holds it
+    let mut first = Patcher::new(&scratch_executable).expect("Failed to parse elf");
+    Patcher::open_unlocked(&scratch_executable, false).expect("Failed to parse unlocked");
+    first.set_runpath("/first", RpathKind::Runpath).unwrap();
+
+    let path = scratch_executable.clone();
+    let second = std::thread::spawn(move || {
+        let mut patcher = Patcher::new(&path)?;
+        patcher.add_needed("libsecond.so")?;
+        patcher.apply()
+    });
+
+    // The second one plans after the first has written, so it finds __gmon_start__ taken
+    // instead of overwriting the new runpath with a stale plan
+    std::thread::sleep(Duration::from_millis(100));
+    first.apply().expect("Failed to apply the first patch");
+    assert!(matches!(
+        second.join().unwrap(),
+        Err(patch::Error::NoDynstrReplacementCandidate {
+            largest_candidate: 0,
+            ..
+        })
+    ));
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/first"));
+}
+
+#[test]
+fn replaced_binary_is_not_patched_after_waiting() {
+    let scratch_executable = scratch_copy("/tmp/lockreplaceddd", "minimal-amd64");
+
+    let mut first = Patcher::new(&scratch_executable).expect("Failed to parse elf");
+    first.set_atomic(true);
+    first.set_runpath("/first", RpathKind::Runpath).unwrap();
+
+    let path = scratch_executable.clone();
+    let second = std::thread::spawn(move || Patcher::new(&path).map(|_| ()));
+
+    // The second one waits on the file that the atomic rename replaces
+    std::thread::sleep(Duration::from_millis(100));
+    first.apply().expect("Failed to apply the first patch");
+    assert!(matches!(
+        second.join().unwrap(),
+        Err(patch::Error::ReplacedWhileLocking { .. })
+    ));
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/first"));
+}
+
+#[test]
+fn patch_from_stdin_to_stdout() {
+    let original = fs::read("./tests/prebuild/minimal-amd64").expect("Failed to read executable");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+        .args(["--bin", "-", "--set-interpreter", TEST_INTERPPATH])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run patchelfdd");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&original)
+        .expect("Failed to write stdin");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for patchelfdd");
+    assert!(output.status.success());
+    assert_eq!(output.stdout.len(), original.len());
+
+    let scratch_dir = PathBuf::from("/tmp/stdindd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("minimal-amd64");
+    fs::write(&scratch_executable, &output.stdout).expect("Failed to write executable");
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+}
+
+#[test]
+fn skipped_binary_passes_through_stdout() {
+    let original = fs::read("./tests/prebuild/minimal-amd64").expect("Failed to read executable");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+        .args(["--bin", "-", "--set-interpreter", TEST_INTERPPATH])
+        .args(["--machine-filter", "aarch64"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run patchelfdd");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&original)
+        .expect("Failed to write stdin");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for patchelfdd");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, original);
+}
+
+#[test]
+fn patch_to_stdout_output() {
+    let scratch_executable = scratch_copy("/tmp/stdoutdd", "minimal-amd64");
+    let original = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+        .arg("--bin")
+        .arg(&scratch_executable)
+        .args(["--output", "-", "--set-interpreter", TEST_INTERPPATH])
+        .output()
+        .expect("Failed to run patchelfdd");
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        original
+    );
+    assert!(!Path::new("-").exists());
+
+    let patched_executable = scratch_executable.with_file_name("minimal-amd64.patched");
+    fs::write(&patched_executable, &output.stdout).expect("Failed to write executable");
+    let mut patcher = Patcher::new(&patched_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+
+    assert!(matches!(
+        run(Opts {
+            output: Some(PathBuf::from("-")),
+            set_interpreter: Some(TEST_INTERPPATH.to_string()),
+            verify: true,
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::UnsupportedWithStdout { option }) if option == "--verify"
+    ));
+}
+
+#[test]
+fn patch_to_output() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/outputdd", "minimal-amd64");
+    let output = scratch_executable.with_file_name("minimal-amd64.patched");
+    let _ = fs::remove_file(&output);
+
+    // The original does not need to be writable
+    fs::set_permissions(&scratch_executable, fs::Permissions::from_mode(0o555))
+        .expect("Failed to set permissions");
+
+    run(Opts {
+        set_interpreter: Some(TEST_INTERPPATH.to_string()),
+        output: Some(output.clone()),
+        verify: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    assert_eq!(
+        fs::read(&scratch_executable).unwrap(),
+        fs::read("./tests/prebuild/minimal-amd64").unwrap()
+    );
+    assert_eq!(
+        fs::metadata(&output).unwrap().permissions().mode() & 0o777,
+        0o555
+    );
+
+    let mut patcher = Patcher::new(&output).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+
+    Ok(())
+}
+
+#[test]
+fn force_converts_rpath_to_runpath() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/rpathdd", "minimal-amd64");
+
+    run(Opts {
+        set_rpath: Some("/old".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    match run(Opts {
+        set_runpath: Some("/new".to_string()),
+        ..opts_for(&scratch_executable)
+    }) {
+        Err(Error::RunpathAlreadySet {
+            kind: RpathKind::Rpath,
+        }) => {}
+        other => panic!("Expected RunpathAlreadySet, got {:?}", other),
+    }
+
+    run(Opts {
+        set_runpath: Some("/new".to_string()),
+        force: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/new"));
+    assert!(patcher
+        .elf
+        .dynamic_contains(RpathKind::Runpath.d_tag())
+        .unwrap());
+    assert!(!patcher
+        .elf
+        .dynamic_contains(RpathKind::Rpath.d_tag())
+        .unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn empty_runpath_removes_it() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/emptyrunpathdd", "minimal-amd64");
+
+    // Same as --remove-runpath without a runpath
+    match run(Opts {
+        set_runpath: Some(String::new()),
+        ..opts_for(&scratch_executable)
+    }) {
+        Err(Error::PatchElf {
+            source: patch::Error::NoRunpathToRemove,
+        }) => {}
+        other => panic!("Expected NoRunpathToRemove, got {:?}", other),
+    }
+
+    run(Opts {
+        set_runpath: Some("/old".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    run(Opts {
+        set_runpath: Some(String::new()),
+        verify: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap(), None);
+    assert!(!patcher
+        .elf
+        .dynamic_contains(RpathKind::Runpath.d_tag())
+        .unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn convert_rpath_keeps_the_path() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/convertdd", "minimal-amd64");
+
+    match run(Opts {
+        convert_rpath_to_runpath: true,
+        ..opts_for(&scratch_executable)
+    }) {
+        Err(Error::PatchElf {
+            source: patch::Error::NoRpathToConvert,
+        }) => {}
+        other => panic!("Expected NoRpathToConvert, got {:?}", other),
+    }
+
+    run(Opts {
+        set_rpath: Some("/old".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    run(Opts {
+        convert_rpath_to_runpath: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/old"));
+    assert!(patcher
+        .elf
+        .dynamic_contains(RpathKind::Runpath.d_tag())
+        .unwrap());
+    assert!(!patcher
+        .elf
+        .dynamic_contains(RpathKind::Rpath.d_tag())
+        .unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn convert_rpath_next_to_runpath_keeps_one_entry() -> Result<(), Error> {
+    let scratch_dir = PathBuf::from("/tmp/convertbothdd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+
+    // Runpath entries in the first spare slots, pointing at __gmon_start__ (1) or libc.so.6 (16)
+    let write_elf = |name: &str, entries: [(i64, u64); 3]| {
+        let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+        for (slot, (d_tag, d_val)) in entries.into_iter().enumerate() {
+            let entry_offset = common::DYNAMIC_OFFSET + (slot + 3) * 16;
+            data[entry_offset..entry_offset + 8].copy_from_slice(&(d_tag as u64).to_le_bytes());
+            data[entry_offset + 8..entry_offset + 16].copy_from_slice(&d_val.to_le_bytes());
+        }
+        let scratch_executable = scratch_dir.join(name);
+        fs::write(&scratch_executable, &data).expect("Failed to write elf");
+        scratch_executable
+    };
+
+    // The DT_RUNPATH the loader already uses wins over the DT_RPATH entries
+    let both = write_elf(
+        "rpath-and-runpath-amd64",
+        [
+            (elf::abi::DT_RPATH, 1),
+            (elf::abi::DT_RUNPATH, 16),
+            (elf::abi::DT_RPATH, 1),
+        ],
+    );
+    // Only the first of several DT_RPATH entries is converted
+    let two_rpaths = write_elf(
+        "two-rpaths-amd64",
+        [
+            (elf::abi::DT_RPATH, 16),
+            (elf::abi::DT_RPATH, 1),
+            (elf::abi::DT_DEBUG, 0),
+        ],
+    );
+
+    for scratch_executable in [both, two_rpaths] {
+        run(Opts {
+            convert_rpath_to_runpath: true,
+            ..opts_for(&scratch_executable)
+        })?;
+
+        let mut elf =
+            sparse_elf::SparseElf::new(&scratch_executable).expect("Failed to parse patched elf");
+        assert_eq!(elf.runpaths().unwrap(), [common::NEEDED]);
+        assert!(elf.dynamic_contains(RpathKind::Runpath.d_tag()).unwrap());
+        assert!(!elf.dynamic_contains(RpathKind::Rpath.d_tag()).unwrap());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn missing_sections_fall_back_to_segments() -> Result<(), patch::Error> {
+    let scratch_dir = PathBuf::from("/tmp/segmentsdd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("minimal-amd64");
+
+    // Renaming the sections in .shstrtab hides them from a lookup by name
+    let mut data = fs::read("./tests/prebuild/minimal-amd64").expect("Failed to read executable");
+    for (name, hidden_name) in [(".interp\0", ".xnterp\0"), (".dynamic\0", ".xynamic\0")] {
+        let position = data
+            .windows(name.len())
+            .rposition(|w| w == name.as_bytes())
+            .expect("Failed to find section name");
+        data[position..position + name.len()].copy_from_slice(hidden_name.as_bytes());
+    }
+    fs::write(&scratch_executable, data).expect("Failed to write executable");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    assert_eq!(patcher.elf.shdr_interp.unwrap().sh_offset, 0x200);
+    assert_eq!(patcher.elf.shdr_dynamic.sh_offset, 0x6c0);
+    assert_eq!(
+        patcher.elf.interpreter().unwrap(),
+        "/lib64/ld-linux-x86-64.so.2"
+    );
+    assert_eq!(patcher.elf.needed().unwrap(), ["libc.so.6"]);
+
+    // Lookups by name return the located sections and whatever else the elf has
+    assert_eq!(patcher.elf.section_offset(".interp").unwrap(), Some(0x200));
+    assert_eq!(patcher.elf.section_offset(".xnterp").unwrap(), Some(0x200));
+    assert!(patcher.elf.section(".text").unwrap().is_some());
+    assert!(patcher.elf.section(".missing").unwrap().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn patch_without_section_headers() -> Result<(), Error> {
+    let scratch_dir = PathBuf::from("/tmp/nosectionsdd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("minimal-amd64");
+
+    // Clear e_shoff, e_shnum and e_shstrndx of the ELF64 header
+    let mut data = fs::read("./tests/prebuild/minimal-amd64").expect("Failed to read executable");
+    data[0x28..0x30].fill(0);
+    data[0x3c..0x40].fill(0);
+    fs::write(&scratch_executable, data).expect("Failed to write executable");
+
+    run(Opts {
+        set_runpath: Some("/new".to_string()),
+        set_interpreter: Some(TEST_INTERPPATH.to_string()),
+        verify: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/new"));
+    assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+    assert_eq!(patcher.elf.needed().unwrap(), ["libc.so.6"]);
+
+    Ok(())
+}
+
+#[test]
+fn append_runpath_skips_existing_directories() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/appendrunpathdd", "minimal-amd64");
+
+    // Without a runpath, appending sets a new DT_RUNPATH
+    run(Opts {
+        append_runpath: Some("/a:/b".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    // Everything is already there, so nothing has to be sacrificed
+    let status = run(Opts {
+        append_runpath: Some("/b:/a".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+    assert_eq!(status, Status::NothingToDo);
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/a:/b"));
+
+    Ok(())
+}
+
+#[test]
+fn atomic_apply_preserves_permissions() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/atomicdd", "minimal-amd64");
+    fs::set_permissions(&scratch_executable, fs::Permissions::from_mode(0o550))
+        .expect("Failed to set permissions");
+
+    // A file planted under a guessable temporary name is left alone
+    let planted_path = scratch_executable.with_file_name("minimal-amd64.patchelfdd.tmp");
+    fs::write(&planted_path, "planted").expect("Failed to write planted file");
+
+    run(Opts {
+        set_runpath: Some("/new".to_string()),
+        atomic: true,
+        verify: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let metadata = fs::metadata(&scratch_executable).expect("Failed to stat executable");
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o550);
+    assert_eq!(fs::read_to_string(&planted_path).unwrap(), "planted");
+    let temp_files = fs::read_dir(scratch_executable.parent().unwrap())
+        .expect("Failed to list directory")
+        .filter(|entry| {
+            let path = entry.as_ref().unwrap().path();
+            path != planted_path && path.to_string_lossy().ends_with(".patchelfdd.tmp")
+        })
+        .count();
+    assert_eq!(temp_files, 0);
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/new"));
+
+    Ok(())
+}
+
+#[test]
+fn interpreter_tail_is_cleared() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/interptaildd", "minimal-amd64");
+
+    run(Opts {
+        set_interpreter: Some("/ld.so".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    // .interp of minimal-amd64 is at 0x200 with a size of 0x1c
+    let data = fs::read(&scratch_executable).expect("Failed to read executable");
+    assert_eq!(&data[0x200..0x206], b"/ld.so");
+    assert!(data[0x206..0x21c].iter().all(|b| *b == 0));
+
+    Ok(())
+}
+
+#[test]
+fn diff_reads_the_overwritten_bytes() {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-amd64"))
+        .expect("Failed to parse elf");
+    patcher
+        .set_interpreter_path("/ld.so")
+        .expect("Failed to plan interpreter patch");
+
+    let diff = patcher.diff().expect("Failed to read diff");
+    assert_eq!(diff.len(), 1);
+    assert_eq!(diff[0].offset, 0x200);
+    assert_eq!(&diff[0].old[..28], b"/lib64/ld-linux-x86-64.so.2\0");
+    assert_eq!(&diff[0].new[..7], b"/ld.so\0");
+}
+
+#[test]
+fn adjacent_candidates_are_merged() -> Result<(), patch::Error> {
+    let scratch_executable = scratch_copy("/tmp/mergedd", "minimal-amd64");
+
+    // Neither "puts" nor "__libc_start_main" alone fit the runpath, together they span 21 bytes
+    let new_runpath = "/opt/merged/runpath";
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.add_sacrifice_symbol("puts");
+    patcher.add_sacrifice_symbol("__libc_start_main");
+    patcher.set_runpath(new_runpath, RpathKind::Runpath)?;
+    assert_eq!(
+        patcher.sacrifices(),
+        [
+            SacrificeReport {
+                symbol: "puts".to_string(),
+                dynstr_offset: 0x1a
+            },
+            SacrificeReport {
+                symbol: "__libc_start_main".to_string(),
+                dynstr_offset: 0x1f
+            }
+        ]
+    );
+    patcher.apply()?;
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some(new_runpath));
+
+    Ok(())
+}
+
+#[test]
+fn plan_does_not_write() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/plandd", "minimal-amd64");
+
+    let mut patcher = plan(&Opts {
+        set_interpreter: Some("/ld.so".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let planned_patches = patcher.planned_patches();
+    assert_eq!(planned_patches.len(), 1);
+    assert_eq!(planned_patches[0].offset(), 0x200);
+    assert_eq!(&planned_patches[0].data()[..7], b"/ld.so\0");
+    assert_eq!(
+        fs::read(&scratch_executable).unwrap(),
+        fs::read("./tests/prebuild/minimal-amd64").unwrap()
+    );
+
+    patcher.apply().expect("Failed to apply patches");
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.interpreter().unwrap(), "/ld.so");
+
+    Ok(())
+}
+
+#[test]
+fn clear_patches_releases_sacrifices() -> Result<(), patch::Error> {
+    // __gmon_start__ is the only candidate
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-s390x"))?;
+
+    assert_eq!(patcher.set_runpath("/first", RpathKind::Runpath)?, None);
+    assert!(patcher.set_soname("libfirst.so").is_err());
+
+    patcher.clear_patches();
+    assert!(patcher.is_empty());
+    assert!(patcher.sacrifices().is_empty());
+
+    patcher.set_runpath("/second", RpathKind::Runpath)?;
+    assert_eq!(patcher.sacrifices()[0].symbol, "__gmon_start__");
+
+    Ok(())
+}
+
+#[test]
+fn summary_names_the_operations() -> Result<(), patch::Error> {
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-s390x"))?;
+
+    patcher.set_runpath("/opt/be", RpathKind::Runpath)?;
+    patcher.set_osabi(elf::abi::ELFOSABI_LINUX);
+
+    // The string and the .dynamic entry of the runpath, and the EI_OSABI byte
+    assert_eq!(
+        patcher.summary(),
+        "Applied 3 patches: runpath, osabi (sacrificed __gmon_start__)"
+    );
+    assert!(patcher
+        .planned_patches()
+        .iter()
+        .all(|patch| patch.label().is_some()));
+
+    Ok(())
+}
+
+#[test]
+fn dry_run_plans_without_writing() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/dryrundd", "minimal-s390x");
+    let original = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    let opts = Opts {
+        set_runpath: Some("/opt/be".to_string()),
+        json_plan: true,
+        ..OptsBuilder::new()
+            .bin(&scratch_executable)
+            .dry_run(true)
+            .build()
+    };
+
+    // The runpath string goes to the .dynstr offset of __gmon_start__
+    let planned = plan(&opts)?.plan();
+    assert_eq!(planned.len(), 2);
+    assert_eq!(planned[0].offset, 0x111);
+    assert_eq!(planned[0].length, 8);
+    assert_eq!(planned[0].bytes_hex, "2f6f70742f626500");
+    assert!(planned.iter().all(|patch| patch.label == Some("runpath")));
+
+    let json = serde_json::to_value(&planned).expect("Failed to serialize plan");
+    assert_eq!(json[0]["bytes_hex"], "2f6f70742f626500");
+    assert_eq!(json[1]["label"], "runpath");
+
+    assert_eq!(run(opts)?, Status::Planned);
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        original
+    );
+
+    Ok(())
+}
+
+#[test]
+fn rerunning_is_a_no_op() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/rerundd", "minimal-amd64");
+
+    let opts = Opts {
+        set_runpath: Some("/opt/lib".to_string()),
+        set_interpreter: Some(TEST_INTERPPATH.to_string()),
+        set_osabi: Some(elf::abi::ELFOSABI_LINUX),
+        ..opts_for(&scratch_executable)
+    };
+
+    assert_eq!(run(opts.clone())?, Status::Patched);
+    let opts = Opts {
+        add_needed: vec!["libc.so.6".to_string()],
+        ..opts
+    };
+    let patched = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    // The runpath is already set, so --force is not needed either
+    assert_eq!(run(opts.clone())?, Status::NothingToDo);
+    assert!(plan(&opts)?.sacrifices().is_empty());
+    assert_eq!(
+        fs::read(&scratch_executable).expect("Failed to read executable"),
+        patched
+    );
+
+    Ok(())
+}
+
+#[test]
+fn origin_is_written_verbatim() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/origindd", "minimal-s390x");
+
+    run(Opts {
+        set_runpath: Some("$ORIGIN/../lib".to_string()),
+        strict: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    // __gmon_start__ is at .dynstr offset 1, .dynstr starts at 0x110
+    let data = fs::read(&scratch_executable).expect("Failed to read executable");
+    assert_eq!(&data[0x111..0x120], b"$ORIGIN/../lib\0");
+
+    // A misspelled token is refused in strict mode
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-s390x"))
+        .expect("Failed to parse elf");
+    patcher.set_strict(true);
+    assert!(matches!(
+        patcher.set_runpath("$ORIGN/../lib", RpathKind::Runpath),
+        Err(patch::Error::SuspiciousValue { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn relative_runpath_is_origin_based() -> Result<(), Error> {
+    let bin_dir = Path::new("/opt/app/bin");
+    for (dir, expected) in [
+        ("/opt/app/lib", "$ORIGIN/../lib"),
+        ("/opt/app/bin", "$ORIGIN"),
+        ("/opt/app/bin/./plugins/", "$ORIGIN/plugins"),
+        ("/opt/app/lib/../../lib64", "$ORIGIN/../../lib64"),
+        ("/usr/lib", "$ORIGIN/../../../usr/lib"),
+    ] {
+        assert_eq!(
+            patchelfdd::origin_relative_runpath(bin_dir, Path::new(dir))?,
+            expected
+        );
+    }
+    assert!(matches!(
+        patchelfdd::origin_relative_runpath(bin_dir, Path::new("lib")),
+        Err(Error::RelativeRunpathDir { .. })
+    ));
+
+    // The binary is found through a symlink, $ORIGIN is where it really is
+    let scratch_dir = PathBuf::from("/tmp/relativedd");
+    let scratch_executable = scratch_copy("/tmp/relativedd/bin", "minimal-s390x");
+    let link = scratch_dir.join("minimal-link");
+    let _ = fs::remove_file(&link);
+    std::os::unix::fs::symlink(&scratch_executable, &link).expect("Failed to create symlink");
+
+    run(Opts {
+        relative_runpath: Some(scratch_dir.join("lib")),
+        verify: true,
+        ..opts_for(&link)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(
+        patcher.elf.runpath().unwrap().as_deref(),
+        Some("$ORIGIN/../lib")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn profile_fills_unset_options() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/profiledd", "minimal-s390x");
+
+    let profile = scratch_executable.with_file_name("patch.toml");
+    fs::write(
+        &profile,
+        "set_runpath = \"/opt/profile\"\n\
+         set_interpreter = \"/lib/ld-profile.so\"\n\
+         set_osabi = \"linux\"\n",
+    )
+    .expect("Failed to write profile");
+
+    // The interpreter from the command line wins
+    run(Opts {
+        profile: Some(profile.clone()),
+        set_interpreter: Some(TEST_INTERPPATH.to_string()),
+        verify: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(
+        patcher.elf.runpath().unwrap().as_deref(),
+        Some("/opt/profile")
+    );
+    assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+    assert_eq!(patcher.elf.osabi(), elf::abi::ELFOSABI_LINUX);
+    drop(patcher);
+
+    fs::write(&profile, "set_runpth = \"/typo\"\n").expect("Failed to write profile");
+    assert!(matches!(
+        run(Opts {
+            profile: Some(profile.clone()),
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::Profile { .. })
+    ));
+
+    // Options that exclude each other can't be combined through the profile either
+    fs::write(&profile, "remove_needed = \"libc.so.6\"\n").expect("Failed to write profile");
+    assert!(matches!(
+        run(Opts {
+            profile: Some(profile.clone()),
+            set_runpath: Some("/new".to_string()),
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::Profile {
+            source: profile::Error::ConflictingOptions {
+                first: "remove-needed",
+                second: "set-runpath"
+            }
+        })
+    ));
+    fs::write(
+        &profile,
+        "set_runpath = \"/opt/profile\"\nappend_runpath = \"/opt/more\"\n",
+    )
+    .expect("Failed to write profile");
+    assert!(matches!(
+        run(Opts {
+            profile: Some(profile),
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::Profile {
+            source: profile::Error::ConflictingOptions {
+                first: "append-runpath",
+                second: "set-runpath"
+            }
+        })
+    ));
+
+    // The command line parser rejects the same pairs
+    for args in [
+        ["--remove-needed", "libc.so.6", "--set-runpath", "/new"],
+        ["--append-runpath", "/opt/more", "--set-runpath", "/new"],
+    ] {
+        let parsed = Opts::from_iter_safe(
+            ["patchelfdd", "--bin"]
+                .into_iter()
+                .chain([scratch_executable.to_str().unwrap()])
+                .chain(args),
+        );
+        assert!(matches!(
+            parsed,
+            Err(structopt::clap::Error {
+                kind: structopt::clap::ErrorKind::ArgumentConflict,
+                ..
+            })
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn unwritable_binary_is_reported_before_parsing() {
+    let scratch_dir = PathBuf::from("/tmp/unwritabledd");
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join("truncated-amd64");
+
+    // A read-only file that would also fail to parse as an elf
+    let _ = fs::remove_file(&scratch_executable);
+    fs::write(&scratch_executable, b"\x7fELF").expect("Failed to write executable");
+    fs::set_permissions(&scratch_executable, fs::Permissions::from_mode(0o444))
+        .expect("Failed to set permissions");
+
+    // Root ignores the file mode
+    if fs::OpenOptions::new()
+        .write(true)
+        .open(&scratch_executable)
+        .is_ok()
+    {
+        eprintln!("Skipping, a file with mode 0444 is writable for this user");
+        return;
+    }
+
+    match run(Opts {
+        set_runpath: Some("/new".to_string()),
+        ..opts_for(&scratch_executable)
+    }) {
+        Err(Error::NotWritable { reason, .. }) => assert!(reason.starts_with("permission denied")),
+        other => panic!("Expected NotWritable, got {:?}", other),
+    }
+}
+
+#[test]
+fn interpreter_is_copied_from_reference() -> Result<(), Error> {
+    let scratch_executable = PathBuf::from("/tmp/interpfromdd/minimal");
+    common::write_minimal_elf(
+        &scratch_executable,
+        common::Class::Elf64,
+        common::Endian::Little,
+    );
+    let reference = PathBuf::from("./tests/prebuild/minimal-s390x");
+
+    run(Opts {
+        interpreter_from: Some(reference.clone()),
+        verify: true,
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    let mut reference_elf = sparse_elf::SparseElf::new(&reference).expect("Failed to parse elf");
+    assert_eq!(
+        patcher.elf.interpreter().unwrap(),
+        reference_elf.interpreter().unwrap()
+    );
+    // The patcher holds the lock on the binary
+    drop(patcher);
+
+    // The generated interpreter path is longer than .interp of minimal-s390x
+    common::write_minimal_elf(
+        &scratch_executable,
+        common::Class::Elf64,
+        common::Endian::Little,
+    );
+    let scratch_s390x = scratch_copy("/tmp/interpfromdd", "minimal-s390x");
+    assert!(matches!(
+        run(Opts {
+            interpreter_from: Some(scratch_executable.clone()),
+            ..opts_for(&scratch_s390x)
+        }),
+        Err(Error::PatchElf {
+            source: patch::Error::CannotFitInterpreterPath { .. }
+        })
+    ));
+
+    // A relocatable object has no interpreter
+    assert!(matches!(
+        run(Opts {
+            interpreter_from: Some(PathBuf::from("./tests/prebuild/relocatable-amd64.o")),
+            ..opts_for(&scratch_executable)
+        }),
+        Err(Error::ReferenceInterpreter { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn patch_big_endian_s390x() -> Result<(), Error> {
+    let scratch_executable = scratch_copy("/tmp/bigendiandd", "minimal-s390x");
+
+    run(Opts {
+        set_runpath: Some("/opt/be".to_string()),
+        ..opts_for(&scratch_executable)
+    })?;
+
+    // __gmon_start__ is at .dynstr offset 1, .dynstr starts at 0x110
+    let data = fs::read(&scratch_executable).expect("Failed to read executable");
+    assert_eq!(&data[0x111..0x119], b"/opt/be\0");
+
+    // The first DT_NULL is the fifth entry of .dynamic at 0x130
+    assert_eq!(&data[0x170..0x178], &0x1du64.to_be_bytes());
+    assert_eq!(&data[0x178..0x180], &1u64.to_be_bytes());
+
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/opt/be"));
+    assert_eq!(patcher.elf.needed().unwrap(), ["libc.so.6"]);
+    assert_eq!(patcher.elf.dynstr_at(1).unwrap(), "/opt/be");
+
+    assert_eq!(patcher.class(), elf::file::Class::ELF64);
+    assert_eq!(patcher.endianness(), elf::endian::AnyEndian::Big);
+    assert_eq!(
+        patcher
+            .serializer()
+            .bytes_from_unsigned_long(0x1d)
+            .unwrap()
+            .bytes(),
+        0x1du64.to_be_bytes()
+    );
+
+    let summary = patcher.elf.summary();
+    assert_eq!(summary.endianness, elf::endian::AnyEndian::Big);
+    assert_eq!(summary.e_machine, elf::abi::EM_S390);
+    assert_eq!(summary.e_type, elf::abi::ET_DYN);
+
+    Ok(())
+}
+
+#[test]
+fn relocatable_objects_are_rejected() {
+    let result = run(Opts {
+        set_runpath: Some("/new".to_string()),
+        ..opts_for(Path::new("./tests/prebuild/relocatable-amd64.o"))
+    });
+
+    assert!(matches!(
+        result,
+        Err(Error::PatchElf {
+            source: patch::Error::SparseElf {
+                source: sparse_elf::Error::UnsupportedElfType {
+                    e_type: elf::abi::ET_REL
+                }
+            }
+        })
+    ));
+}
+
+#[test]
+fn patch_generated_elfs() -> Result<(), Error> {
+    let scratch_dir = PathBuf::from("/tmp/generateddd");
+
+    for class in [common::Class::Elf32, common::Class::Elf64] {
+        for endian in [common::Endian::Little, common::Endian::Big] {
+            let scratch_executable = scratch_dir.join(format!("minimal-{:?}-{:?}", class, endian));
+            common::write_minimal_elf(&scratch_executable, class, endian);
+
+            let mut elf = sparse_elf::SparseElf::new(&scratch_executable)
+                .expect("Failed to parse generated elf");
+            let stats = elf
+                .dynamic_stats()
+                .expect("Failed to count .dynamic entries");
+            assert_eq!((stats.entries, stats.free_slots()), (7, 3));
+            assert_eq!(elf.find_dynamic(elf::abi::DT_STRTAB).unwrap(), Some(1));
+            assert_eq!(elf.find_dynamic(elf::abi::DT_RUNPATH).unwrap(), None);
+
+            run(OptsBuilder::new()
+                .bin(&scratch_executable)
+                .set_runpath("/new")
+                .set_interpreter(TEST_INTERPPATH)
+                .verify(true)
+                .build())?;
+
+            let mut patcher =
+                Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+            assert_eq!(patcher.elf.runpath().unwrap().as_deref(), Some("/new"));
+            assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+            assert_eq!(patcher.elf.needed().unwrap(), [common::NEEDED]);
+            assert_eq!(patcher.elf.dynamic_stats().unwrap().free_slots(), 2);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn flags_1_bits_are_set_and_cleared() -> Result<(), Error> {
+    let scratch_executable = PathBuf::from("/tmp/flags1dd/minimal");
+    common::write_minimal_elf(
+        &scratch_executable,
+        common::Class::Elf32,
+        common::Endian::Big,
+    );
+
+    // Without DT_FLAGS_1, a spare DT_NULL slot takes the new entry
+    run(Opts {
+        set_flag1: vec![elf::abi::DF_1_ORIGIN as u64, elf::abi::DF_1_NOW as u64],
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut elf = sparse_elf::SparseElf::new(&scratch_executable).expect("Failed to parse elf");
+    assert_eq!(
+        elf.flags_1().unwrap(),
+        Some((elf::abi::DF_1_ORIGIN | elf::abi::DF_1_NOW) as u64)
+    );
+    assert_eq!(elf.dynamic_stats().unwrap().free_slots(), 2);
+
+    run(Opts {
+        clear_flag1: vec![elf::abi::DF_1_NOW as u64],
+        ..opts_for(&scratch_executable)
+    })?;
+
+    let mut elf = sparse_elf::SparseElf::new(&scratch_executable).expect("Failed to parse elf");
+    assert_eq!(elf.flags_1().unwrap(), Some(elf::abi::DF_1_ORIGIN as u64));
+    assert_eq!(elf.dynamic_stats().unwrap().free_slots(), 2);
+
+    let flags = elf.flags().unwrap();
+    assert_eq!(flags.flags, None);
+    assert_eq!(
+        sparse_elf::flag_names(flags.flags_1.unwrap() | 1 << 40, sparse_elf::FLAGS_1_NAMES),
+        ["DF_1_ORIGIN", "0x10000000000"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn needed_entries_are_not_repointed() {
+    let scratch_dir = PathBuf::from("/tmp/repointdd");
+    let scratch_executable = scratch_dir.join("minimal-no-spare-null");
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+
+    // Turn all but the terminating DT_NULL into DT_DEBUG, so there is no spare slot
+    for entry in 3..6 {
+        let offset = common::DYNAMIC_OFFSET + entry * 16;
+        data[offset..offset + 8].copy_from_slice(&(elf::abi::DT_DEBUG as u64).to_le_bytes());
+    }
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    // Sacrifice the DT_NEEDED string, the only entry referencing it is DT_NEEDED
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse generated elf");
+    patcher.set_sacrifice_offset(16);
+
+    assert!(matches!(
+        patcher.set_runpath("/new", RpathKind::Runpath),
+        Err(patch::Error::DynamicEntryInUse {
+            d_tag: elf::abi::DT_NEEDED
+        })
+    ));
+}
+
+#[test]
+fn integer_entries_are_not_repointed() {
+    let scratch_dir = PathBuf::from("/tmp/repointdd");
+    let scratch_executable = scratch_dir.join("minimal-flags-1-collision");
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+
+    // No spare slot is left and DT_FLAGS_1 is DF_1_NOW, the .dynstr offset of __gmon_start__
+    for entry in 3..6 {
+        let offset = common::DYNAMIC_OFFSET + entry * 16;
+        data[offset..offset + 8].copy_from_slice(&(elf::abi::DT_DEBUG as u64).to_le_bytes());
+    }
+    let offset = common::DYNAMIC_OFFSET + 3 * 16;
+    data[offset..offset + 8].copy_from_slice(&(elf::abi::DT_FLAGS_1 as u64).to_le_bytes());
+    data[offset + 8..offset + 16].copy_from_slice(&(elf::abi::DF_1_NOW as u64).to_le_bytes());
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    type Edit = fn(&mut Patcher) -> Result<(), patch::Error>;
+    let edits: [Edit; 3] = [
+        |patcher| patcher.set_runpath("/new", RpathKind::Runpath).map(|_| ()),
+        |patcher| patcher.add_needed("libnew.so"),
+        |patcher| patcher.set_soname("libnew.so").map(|_| ()),
+    ];
+    for edit in edits {
+        let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse generated elf");
+        assert!(matches!(
+            edit(&mut patcher),
+            Err(patch::Error::DynamicEntryInUse {
+                d_tag: elf::abi::DT_FLAGS_1
+            })
+        ));
+    }
+}
+
+#[test]
+fn dynamic_strategy_controls_repointing() -> Result<(), patch::Error> {
+    let scratch_dir = PathBuf::from("/tmp/repointdd");
+    let scratch_executable = scratch_dir.join("minimal-debug-gmon");
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+
+    // Only the terminating DT_NULL is left, a DT_RPATH references __gmon_start__
+    for entry in 3..6 {
+        let offset = common::DYNAMIC_OFFSET + entry * 16;
+        data[offset..offset + 8].copy_from_slice(&(elf::abi::DT_DEBUG as u64).to_le_bytes());
+    }
+    let offset = common::DYNAMIC_OFFSET + 3 * 16;
+    data[offset..offset + 8].copy_from_slice(&(elf::abi::DT_RPATH as u64).to_le_bytes());
+    data[offset + 8..offset + 16].copy_from_slice(&1u64.to_le_bytes());
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    patcher.set_dynamic_strategy(DynamicStrategy::SpareSlot);
+    assert!(matches!(
+        patcher.set_runpath("/new", RpathKind::Runpath),
+        Err(patch::Error::NoSpareDynamicSlot)
+    ));
+
+    patcher.clear_patches();
+    patcher.set_dynamic_strategy(DynamicStrategy::Auto);
+    patcher.set_runpath("/new", RpathKind::Runpath)?;
+    assert!(patcher
+        .planned_patches()
+        .iter()
+        .any(|patch| patch.offset() == common::DYNAMIC_OFFSET + 3 * 16));
+
+    // Repoint ignores the spare slots, and nothing in .dynamic references __gmon_start__
+    let mut patcher = Patcher::new(&PathBuf::from("./tests/prebuild/minimal-s390x"))?;
+    patcher.set_dynamic_strategy(DynamicStrategy::Repoint);
+    assert!(matches!(
+        patcher.set_runpath("/new", RpathKind::Runpath),
+        Err(patch::Error::NoApplicableDynamicEntry)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn static_binaries_are_reported() {
+    let scratch_executable = PathBuf::from("/tmp/staticdd/minimal-static");
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+
+    // Turn PT_INTERP and PT_DYNAMIC, the second and third program header, into PT_NULL
+    for phdr in 1..3 {
+        let offset = 64 + phdr * 56;
+        data[offset..offset + 4].copy_from_slice(&elf::abi::PT_NULL.to_le_bytes());
+    }
+    fs::create_dir_all(scratch_executable.parent().unwrap()).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    assert!(matches!(
+        Patcher::new(&scratch_executable),
+        Err(patch::Error::SparseElf {
+            source: sparse_elf::Error::StaticallyLinked
+        })
+    ));
+}
+
+#[test]
+fn overlapping_sections_are_not_patched() {
+    let scratch_executable = PathBuf::from("/tmp/overlapdd/minimal-overlap");
+    common::write_minimal_elf(
+        &scratch_executable,
+        common::Class::Elf64,
+        common::Endian::Little,
+    );
+    let original = fs::read(&scratch_executable).expect("Failed to read executable");
+
+    // Let .interp claim the start of .dynstr
+    let mut patcher = Patcher::new(&scratch_executable).expect("Failed to parse generated elf");
+    patcher.elf.shdr_interp.as_mut().unwrap().sh_size = 0x30;
+    patcher
+        .set_runpath("/new", RpathKind::Runpath)
+        .expect("Failed to plan runpath");
+
+    assert!(matches!(
+        patcher.apply(),
+        Err(patch::Error::SectionsOverlap { .. })
+    ));
+    assert_eq!(fs::read(&scratch_executable).unwrap(), original);
+}
+
+#[test]
+fn long_interpreter_is_relocated() -> Result<(), Error> {
+    let long_interpreter = "/opt/a/rather/long/path/to/the/dynamic/loader/ld.so";
+
+    for endian in [common::Endian::Little, common::Endian::Big] {
+        let scratch_executable =
+            PathBuf::from(format!("/tmp/relocateinterpdd/minimal-Elf32-{:?}", endian));
+        common::write_minimal_elf(&scratch_executable, common::Class::Elf32, endian);
+
+        match run(Opts {
+            set_interpreter: Some(long_interpreter.to_string()),
+            ..opts_for(&scratch_executable)
+        }) {
+            Err(Error::PatchElf {
+                source: patch::Error::CannotFitInterpreterPath { .. },
+            }) => {}
+            other => panic!("Expected CannotFitInterpreterPath, got {:?}", other),
+        }
+
+        run(Opts {
+            set_interpreter: Some(long_interpreter.to_string()),
+            allow_relocate_interp: true,
+            verify: true,
+            ..opts_for(&scratch_executable)
+        })?;
+
+        // The gap between the program headers and the old .interp is used
+        let patcher = Patcher::new(&scratch_executable).expect("Failed to parse patched elf");
+        let phdr_interp = patcher.elf.segment(elf::abi::PT_INTERP).unwrap();
+        assert!(phdr_interp.p_offset < 0x100);
+        assert_eq!(phdr_interp.p_filesz, long_interpreter.len() as u64 + 1);
+        assert_eq!(phdr_interp.p_vaddr, phdr_interp.p_offset);
+        assert_eq!(
+            patcher.elf.shdr_interp.unwrap().sh_offset,
+            phdr_interp.p_offset
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn empty_dynstr_entries_terminate() -> Result<(), patch::Error> {
+    let scratch_executable = PathBuf::from("/tmp/emptydynstrdd/minimal-empty-dynstr");
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+
+    // Only empty strings left, the walk advances one byte at a time
+    data[common::DYNSTR_OFFSET..common::DYNAMIC_OFFSET].fill(0);
+    fs::create_dir_all(scratch_executable.parent().unwrap()).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    let entries = patcher.elf.dynstr_entries().unwrap();
+    assert!(entries.iter().all(|(_, entry)| entry.is_empty()));
+    assert!(patcher.list_candidates()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn one_byte_dynstr_is_reported() -> Result<(), patch::Error> {
+    let scratch_executable = PathBuf::from("/tmp/emptydynstrdd/minimal-one-byte-dynstr");
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+
+    // sh_size of .dynstr, the third section header
+    let shoff = u64::from_le_bytes(data[0x28..0x30].try_into().unwrap()) as usize;
+    let sh_size_offset = shoff + 2 * 64 + 32;
+    data[sh_size_offset..sh_size_offset + 8].copy_from_slice(&1u64.to_le_bytes());
+    fs::create_dir_all(scratch_executable.parent().unwrap()).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    let mut patcher = Patcher::new(&scratch_executable)?;
+    let error = patcher
+        .set_runpath("/a", RpathKind::Runpath)
+        .expect_err("A one byte .dynstr has no candidate");
+    assert!(matches!(
+        error,
+        patch::Error::DegenerateDynstr { dynstr_size: 1 }
+    ));
+    assert_eq!(
+        error.to_string(),
+        ".dynstr has a size of 1, it holds no entries besides the leading empty string"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn oversized_interp_is_rejected() {
+    let scratch_executable = PathBuf::from("/tmp/oversizeddd/minimal-oversized-interp");
+    let mut data = common::minimal_elf(common::Class::Elf64, common::Endian::Little);
+
+    // .interp is the first section after the null one, sh_size is at +0x20 of its header
+    let shoff = u64::from_le_bytes(data[0x28..0x30].try_into().unwrap()) as usize;
+    let sh_size_offset = shoff + 64 + 0x20;
+    data[sh_size_offset..sh_size_offset + 8].copy_from_slice(&0x7000_0000_0000u64.to_le_bytes());
+    fs::create_dir_all(scratch_executable.parent().unwrap()).expect("Failed to create directory");
+    fs::write(&scratch_executable, &data).expect("Failed to write executable");
+
+    // This used to allocate the claimed size for the interpreter patch
+    assert!(matches!(
+        Patcher::new(&scratch_executable),
+        Err(patch::Error::SparseElf {
+            source: sparse_elf::Error::SectionOutOfBounds { .. }
+        })
+    ));
+}
+
+/// Points the runpath of `scratch_executable` at its directory and sets [`TEST_INTERPPATH`], then
+/// checks both by parsing the patched elf again
+fn test_patch(scratch_executable: &Path) -> Result<(), Error> {
+    let scratch_dir = scratch_executable
+        .parent()
+        .expect("Failed to get scratch directory")
+        .to_string_lossy()
+        .to_string();
+
+    run(Opts {
+        set_runpath: Some(scratch_dir.clone()),
+        set_interpreter: Some(TEST_INTERPPATH.to_string()),
+        ..opts_for(scratch_executable)
+    })?;
+
+    let mut patcher =
+        Patcher::new(&scratch_executable.to_path_buf()).expect("Failed to parse patched elf");
+    assert_eq!(patcher.elf.runpath().unwrap(), Some(scratch_dir));
+    assert_eq!(patcher.elf.interpreter().unwrap(), TEST_INTERPPATH);
+    assert_eq!(patcher.elf.needed().unwrap(), ["libc.so.6"]);
+
+    Ok(())
+}
+
+/// Copies the prebuild `name` to a fresh file in `scratch_dir`
+fn scratch_copy(scratch_dir: &str, name: &str) -> PathBuf {
+    let scratch_dir = PathBuf::from(scratch_dir);
+    fs::create_dir_all(&scratch_dir).expect("Failed to create directory");
+    let scratch_executable = scratch_dir.join(name);
+    fs::copy(
+        Path::new("./tests/prebuild").join(name),
+        &scratch_executable,
+    )
+    .expect("Failed to copy executable");
+    scratch_executable
+}
+
+/// Runs the patchelfdd binary with `args` on `bin` and returns its stdout
+fn query(bin: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_patchelfdd"))
+        .arg("--bin")
+        .arg(bin)
+        .args(args)
+        .output()
+        .expect("Failed to run patchelfdd");
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn opts_for(bin: &Path) -> Opts {
+    OptsBuilder::new().bin(bin).build()
+}